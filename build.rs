@@ -1,7 +1,19 @@
+use std::env;
 use std::io;
 mod generate_ast;
 use generate_ast::*;
 
+const AST_SPEC: &str = "grammar/ast.def";
+
 fn main() -> io::Result<()> {
-    generate_ast("src")
+    println!("cargo:rerun-if-changed={AST_SPEC}");
+    println!("cargo:rerun-if-changed=generate_ast/mod.rs");
+    println!("cargo:rerun-if-changed=build.rs");
+
+    let out_dir = env::var("OUT_DIR").expect("OUT_DIR is set by cargo");
+    if let Err(e) = generate_ast(AST_SPEC, &out_dir) {
+        eprintln!("error: {e}");
+        std::process::exit(1);
+    }
+    Ok(())
 }
@@ -1,74 +1,238 @@
-use std::fs::File;
+use std::fmt;
+use std::fs::{self, File};
 use std::io::{self, Write};
 
+#[derive(Debug)]
+struct Field {
+    name: String,
+    ftype: String,
+}
+
 #[derive(Debug)]
 struct TreeType {
     base_class_name: String,
     class_name: String,
-    fields: Vec<String>,
-}
-
-pub fn generate_ast(output_dir: &str) -> io::Result<()> {
-    define_ast(
-        output_dir,
-        "Expr",
-        &["error", "token", "literal", "rc"],
-        &[
-            "Binary   : Rc<Expr> left, Token operator, Rc<Expr> right",
-            "Grouping : Rc<Expr> expression",
-            "Literal  : Option<Literal> value",
-            "Unary    : Token operator, Rc<Expr> right",
-        ],
-    )?;
+    fields: Vec<Field>,
+}
 
-    define_ast(
-        output_dir,
-        "Stmt",
-        &["error", "expr", "token", "rc"],
-        &[
-            "Expression : Rc<Expr> expression",
-            "Print      : Rc<Expr> expression",
-        ],
-    )?;
+/// One `tree` block of the spec file.
+#[derive(Debug)]
+struct TreeDef {
+    base_name: String,
+    imports: Vec<String>,
+    types: Vec<TreeType>,
+}
+
+#[derive(Debug)]
+pub struct SpecError {
+    path: String,
+    line: usize,
+    message: String,
+}
+
+impl fmt::Display for SpecError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.path, self.line, self.message)
+    }
+}
+
+impl From<SpecError> for io::Error {
+    fn from(err: SpecError) -> io::Error {
+        io::Error::new(io::ErrorKind::InvalidData, err.to_string())
+    }
+}
+
+pub fn generate_ast(spec_path: &str, output_dir: &str) -> io::Result<()> {
+    let spec = fs::read_to_string(spec_path)?;
+    let trees = parse_spec(spec_path, &spec)?;
+    let tree_names: Vec<&str> = trees.iter().map(|t| t.base_name.as_str()).collect();
+    for tree in &trees {
+        define_ast(spec_path, output_dir, tree, &tree_names)?;
+    }
+    define_arena(spec_path, output_dir, &trees, &tree_names)?;
     Ok(())
 }
 
+fn parse_spec(path: &str, spec: &str) -> Result<Vec<TreeDef>, SpecError> {
+    let mut trees: Vec<TreeDef> = Vec::new();
+
+    for (index, raw) in spec.lines().enumerate() {
+        let error = |message: String| SpecError {
+            path: path.to_string(),
+            line: index + 1,
+            message,
+        };
+        let line = raw.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        if !raw.starts_with(char::is_whitespace) {
+            let header = line
+                .strip_prefix("tree ")
+                .ok_or_else(|| error(format!("expected 'tree <Base> uses ...', found '{line}'")))?;
+            let (base_name, imports) = match header.split_once(" uses ") {
+                Some((base_name, imports)) => (base_name.trim(), imports),
+                None => (header.trim(), ""),
+            };
+            if !is_identifier(base_name) {
+                return Err(error(format!("invalid tree name '{base_name}'")));
+            }
+            if trees.iter().any(|t| t.base_name == base_name) {
+                return Err(error(format!("tree '{base_name}' is defined twice")));
+            }
+            let mut import_list = Vec::new();
+            for import in imports.split(',').map(str::trim).filter(|i| !i.is_empty()) {
+                if !is_identifier(import) {
+                    return Err(error(format!("invalid module name '{import}'")));
+                }
+                import_list.push(import.to_string());
+            }
+            trees.push(TreeDef {
+                base_name: base_name.to_string(),
+                imports: import_list,
+                types: Vec::new(),
+            });
+            continue;
+        }
+
+        let known_trees: Vec<String> = trees.iter().map(|t| t.base_name.clone()).collect();
+        let tree = trees
+            .last_mut()
+            .ok_or_else(|| error("node definition outside of a 'tree' block".to_string()))?;
+        let (node_name, args) = line
+            .split_once(':')
+            .ok_or_else(|| error(format!("expected '<Node> : <fields>', found '{line}'")))?;
+        let node_name = node_name.trim();
+        if !is_identifier(node_name) {
+            return Err(error(format!("invalid node name '{node_name}'")));
+        }
+        if tree.types.iter().any(|t| t.base_class_name == node_name) {
+            return Err(error(format!(
+                "node '{node_name}' is defined twice in tree '{}'",
+                tree.base_name
+            )));
+        }
+
+        let mut fields: Vec<Field> = Vec::new();
+        for arg in split_fields(args) {
+            let (ftype, name) = arg
+                .rsplit_once(char::is_whitespace)
+                .ok_or_else(|| error(format!("expected '<Type> <name>', found '{arg}'")))?;
+            let (ftype, name) = (ftype.trim(), name.trim());
+            if !is_identifier(name) {
+                return Err(error(format!("invalid field name '{name}'")));
+            }
+            if !is_known_type(ftype, &known_trees) {
+                return Err(error(format!(
+                    "unknown type '{ftype}' for field '{name}' of '{node_name}'"
+                )));
+            }
+            if fields.iter().any(|f| f.name == name) {
+                return Err(error(format!(
+                    "field '{name}' is defined twice in '{node_name}'"
+                )));
+            }
+            fields.push(Field {
+                name: name.to_string(),
+                ftype: ftype.to_string(),
+            });
+        }
+        if fields.is_empty() {
+            return Err(error(format!("node '{node_name}' has no fields")));
+        }
+
+        tree.types.push(TreeType {
+            base_class_name: node_name.to_string(),
+            class_name: format!("{node_name}{}", tree.base_name),
+            fields,
+        });
+    }
+
+    if let Some(tree) = trees.iter().find(|t| t.types.is_empty()) {
+        return Err(SpecError {
+            path: path.to_string(),
+            line: spec.lines().count(),
+            message: format!("tree '{}' has no nodes", tree.base_name),
+        });
+    }
+    Ok(trees)
+}
+
+/// Splits `Rc<Expr> left, HashMap<K, V> map` on the commas outside of `<>`.
+fn split_fields(args: &str) -> Vec<&str> {
+    let mut fields = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+    for (i, ch) in args.char_indices() {
+        match ch {
+            '<' => depth += 1,
+            '>' => depth -= 1,
+            ',' if depth == 0 => {
+                fields.push(args[start..i].trim());
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    fields.push(args[start..].trim());
+    fields.into_iter().filter(|f| !f.is_empty()).collect()
+}
+
+/// Field types the generated code knows how to print, compare and convert
+/// to JSON, besides references to trees.
+const LEAF_TYPES: &[&str] = &["Token", "Literal", "String", "bool"];
+
+/// A leaf type or `Rc<Base>` of a tree defined so far, optionally inside
+/// `Option<>` or `Vec<>`.
+fn is_known_type(ftype: &str, trees: &[String]) -> bool {
+    let inner = ftype
+        .strip_prefix("Option<")
+        .or_else(|| ftype.strip_prefix("Vec<"))
+        .and_then(|t| t.strip_suffix('>'))
+        .unwrap_or(ftype);
+    match inner.strip_prefix("Rc<").and_then(|t| t.strip_suffix('>')) {
+        Some(tree) => trees.iter().any(|t| t == tree),
+        None => LEAF_TYPES.contains(&inner),
+    }
+}
+
+fn is_identifier(name: &str) -> bool {
+    let mut chars = name.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
 fn define_ast(
+    spec_path: &str,
     output_dir: &str,
-    base_name: &str,
-    imports: &[&str],
-    types: &[&str],
+    tree: &TreeDef,
+    tree_names: &[&str],
 ) -> io::Result<()> {
+    let base_name = tree.base_name.as_str();
+    let tree_types = &tree.types;
     let path = format!("{output_dir}/{}.rs", base_name.to_lowercase());
     let mut file = File::create(path)?;
-    let mut tree_types = Vec::new();
 
-    for i in imports {
-        if i == &"rc" {
+    writeln!(
+        file,
+        "// Generated by build.rs from {spec_path}. Do not edit.\n"
+    )?;
+    writeln!(file, "use std::fmt;")?;
+    writeln!(file, "use std::hash::{{Hash, Hasher}};")?;
+    writeln!(file, "use crate::json::*;")?;
+    writeln!(file, "use crate::structural_eq::*;")?;
+    for i in &tree.imports {
+        if i == "rc" {
             writeln!(file, "use std::rc::Rc;")?;
         } else {
             writeln!(file, "use crate::{}::*;", i)?;
         }
     }
 
-    for ttype in types {
-        let (base_class_name, args) = ttype.split_once(':').unwrap();
-        let class_name = format!("{}{}", base_class_name.trim(), base_name);
-        let arg_split = args.split(',');
-        let mut fields = Vec::new();
-        for arg in arg_split {
-            let (t2type, name) = arg.trim().split_once(' ').unwrap();
-            fields.push(format!("{}: {}", name, t2type));
-        }
-        tree_types.push(TreeType {
-            base_class_name: base_class_name.trim().to_string(),
-            class_name,
-            fields,
-        });
-    }
-
-    writeln!(file, "\npub enum {base_name} {{")?;
-    for t in &tree_types {
+    writeln!(file, "\n#[derive(Debug, Clone)]")?;
+    writeln!(file, "pub enum {base_name} {{")?;
+    for t in tree_types {
         writeln!(file, "    {}(Rc<{}>),", t.base_class_name, t.class_name)?;
     }
     writeln!(file, "}}\n")?;
@@ -76,7 +240,7 @@ fn define_ast(
     writeln!(file, "impl PartialEq for {} {{", base_name)?;
     writeln!(file, "    fn eq(&self, other: &Self) -> bool {{")?;
     writeln!(file, "        match (self, other) {{")?;
-    for t in &tree_types {
+    for t in tree_types {
         writeln!(
             file,
             "            ({0}::{1}(a), {0}::{1}(b)) => Rc::ptr_eq(a, b),",
@@ -88,45 +252,655 @@ fn define_ast(
     writeln!(file, "    }}")?;
     writeln!(file, "}}\n\nimpl Eq for {}{{}}\n", base_name)?;
 
-    writeln!(file, "impl {} {{", base_name)?;
-    writeln!(file,
-        "    pub fn accept<T>(&self, {}_visitor: &dyn {base_name}Visitor<T>) -> Result<T, JialoxError> {{", 
-        base_name.to_lowercase()
+    // Hash agrees with the pointer-identity PartialEq above, so a node can
+    // key a side table such as resolved variable depths.
+    writeln!(file, "impl Hash for {} {{", base_name)?;
+    writeln!(file, "    fn hash<H: Hasher>(&self, state: &mut H) {{")?;
+    writeln!(file, "        match self {{")?;
+    for t in tree_types {
+        writeln!(
+            file,
+            "            {}::{}(a) => std::ptr::hash(Rc::as_ptr(a), state),",
+            base_name, t.base_class_name
+        )?;
+    }
+    writeln!(file, "        }}")?;
+    writeln!(file, "    }}")?;
+    writeln!(file, "}}\n")?;
+
+    writeln!(file, "impl StructuralEq for {} {{", base_name)?;
+    writeln!(file, "    fn structural_eq(&self, other: &Self) -> bool {{")?;
+    writeln!(file, "        match (self, other) {{")?;
+    for t in tree_types {
+        writeln!(
+            file,
+            "            ({0}::{1}(a), {0}::{1}(b)) => a.structural_eq(b),",
+            base_name, t.base_class_name
+        )?;
+    }
+    writeln!(file, "            _ => false,")?;
+    writeln!(file, "        }}")?;
+    writeln!(file, "    }}")?;
+    writeln!(file, "}}\n")?;
+
+    writeln!(file, "impl fmt::Display for {} {{", base_name)?;
+    writeln!(
+        file,
+        "    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {{"
     )?;
     writeln!(file, "        match self {{")?;
-    for t in &tree_types {
+    for t in tree_types {
         writeln!(
             file,
-            "            {0}::{1}(v) => {3}_visitor.visit_{2}_{3}(v),",
-            base_name,
-            t.base_class_name,
-            t.base_class_name.to_lowercase(),
-            base_name.to_lowercase(),
+            "            {}::{}(v) => write!(f, \"{{v}}\"),",
+            base_name, t.base_class_name
         )?;
     }
     writeln!(file, "        }}")?;
     writeln!(file, "    }}")?;
     writeln!(file, "}}\n")?;
 
-    for t in &tree_types {
+    define_json(&mut file, tree)?;
+
+    writeln!(file, "impl {} {{", base_name)?;
+    for (method, visitor) in [("accept", "&dyn"), ("accept_mut", "&mut dyn")] {
+        if method == "accept_mut" {
+            writeln!(file)?;
+        }
+        let trait_name = if method == "accept" {
+            format!("{base_name}Visitor")
+        } else {
+            format!("{base_name}VisitorMut")
+        };
+        writeln!(file,
+            "    pub fn {method}<T, E>(&self, {}_visitor: {visitor} {trait_name}<T, E>) -> Result<T, E> {{",
+            base_name.to_lowercase()
+        )?;
+        writeln!(file, "        match self {{")?;
+        for t in tree_types {
+            writeln!(
+                file,
+                "            {0}::{1}(v) => {3}_visitor.visit_{2}_{3}(v),",
+                base_name,
+                t.base_class_name,
+                to_snake_case(&t.base_class_name),
+                base_name.to_lowercase(),
+            )?;
+        }
+        writeln!(file, "        }}")?;
+        writeln!(file, "    }}")?;
+    }
+
+    for t in tree_types {
+        let params: Vec<String> = t
+            .fields
+            .iter()
+            .map(|f| format!("{}: {}", f.name, f.ftype))
+            .collect();
+        let names: Vec<&str> = t.fields.iter().map(|f| f.name.as_str()).collect();
+        writeln!(
+            file,
+            "\n    pub fn {}({}) -> {} {{",
+            constructor_name(&t.base_class_name),
+            params.join(", "),
+            base_name
+        )?;
+        writeln!(
+            file,
+            "        {}::{}(Rc::new({} {{ {} }}))",
+            base_name,
+            t.base_class_name,
+            t.class_name,
+            names.join(", ")
+        )?;
+        writeln!(file, "    }}")?;
+    }
+    writeln!(file, "}}\n")?;
+
+    for t in tree_types {
+        writeln!(file, "#[derive(Debug, Clone)]")?;
         writeln!(file, "pub struct {} {{", t.class_name)?;
         for f in &t.fields {
-            writeln!(file, "    pub {},", f)?;
+            writeln!(file, "    pub {}: {},", f.name, f.ftype)?;
+        }
+        writeln!(file, "}}\n")?;
+
+        let comparisons: Vec<String> = t
+            .fields
+            .iter()
+            .map(|f| format!("self.{0}.structural_eq(&other.{0})", f.name))
+            .collect();
+        writeln!(file, "impl StructuralEq for {} {{", t.class_name)?;
+        writeln!(file, "    fn structural_eq(&self, other: &Self) -> bool {{")?;
+        writeln!(file, "        {}", comparisons.join("\n            && "))?;
+        writeln!(file, "    }}")?;
+        writeln!(file, "}}\n")?;
+
+        // Prints as an S-expression, e.g. `(Binary (Literal 1) + (Literal 2))`.
+        writeln!(file, "impl fmt::Display for {} {{", t.class_name)?;
+        writeln!(
+            file,
+            "    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {{"
+        )?;
+        writeln!(file, "        write!(f, \"({}\")?;", t.base_class_name)?;
+        for f in &t.fields {
+            writeln!(file, "        {}", display_field(&f.ftype, &f.name))?;
         }
+        writeln!(file, "        write!(f, \")\")")?;
+        writeln!(file, "    }}")?;
         writeln!(file, "}}\n")?;
     }
 
-    writeln!(file, "pub trait {}Visitor<T> {{", base_name)?;
-    for t in &tree_types {
+    for (trait_name, receiver) in [("Visitor", "&self"), ("VisitorMut", "&mut self")] {
+        writeln!(
+            file,
+            "pub trait {base_name}{trait_name}<T, E = JialoxError> {{"
+        )?;
+        for t in tree_types {
+            writeln!(
+                file,
+                "    fn visit_{0}_{1}({receiver}, {1}: &{2}) -> Result<T, E>;",
+                to_snake_case(&t.base_class_name),
+                base_name.to_lowercase(),
+                t.class_name
+            )?;
+        }
+        writeln!(file, "}}\n")?;
+    }
+
+    define_walker(&mut file, tree, tree_names)?;
+
+    Ok(())
+}
+
+/// Emits `ToJson` and `FromJson` for the tree: each node is an object with
+/// its `kind` and one member per field, loaded back through the node's
+/// constructor.
+fn define_json(file: &mut File, tree: &TreeDef) -> io::Result<()> {
+    let base_name = tree.base_name.as_str();
+
+    writeln!(file, "impl ToJson for {base_name} {{")?;
+    writeln!(file, "    fn to_json(&self) -> Json {{")?;
+    writeln!(file, "        match self {{")?;
+    for t in &tree.types {
+        let fields: Vec<String> = t
+            .fields
+            .iter()
+            .map(|f| format!("(\"{0}\", v.{0}.to_json())", f.name))
+            .collect();
+        writeln!(
+            file,
+            "            {base_name}::{0}(v) => Json::node(\"{0}\", vec![{1}]),",
+            t.base_class_name,
+            fields.join(", ")
+        )?;
+    }
+    writeln!(file, "        }}")?;
+    writeln!(file, "    }}")?;
+    writeln!(file, "}}\n")?;
+
+    writeln!(file, "impl FromJson for {base_name} {{")?;
+    writeln!(
+        file,
+        "    fn from_json(json: &Json) -> Result<Self, JsonError> {{"
+    )?;
+    writeln!(file, "        match json.kind()? {{")?;
+    for t in &tree.types {
+        let fields: Vec<String> = t
+            .fields
+            .iter()
+            .map(|f| format!("FromJson::from_json(json.field(\"{}\")?)?", f.name))
+            .collect();
+        writeln!(
+            file,
+            "            \"{}\" => Ok({base_name}::{}({})),",
+            t.base_class_name,
+            constructor_name(&t.base_class_name),
+            fields.join(", ")
+        )?;
+    }
+    writeln!(
+        file,
+        "            kind => Err(JsonError::new(format!(\"Unknown {base_name} kind '{{kind}}'.\"))),"
+    )?;
+    writeln!(file, "        }}")?;
+    writeln!(file, "    }}")?;
+    writeln!(file, "}}\n")?;
+    Ok(())
+}
+
+/// Emits `<Base>Walker`, a visitor whose methods all default to visiting the
+/// node's children, and the `walk_<node>_<base>_children` functions those
+/// defaults call. A pass overrides only the nodes it cares about and calls
+/// the matching function to keep descending.
+fn define_walker(file: &mut File, tree: &TreeDef, tree_names: &[&str]) -> io::Result<()> {
+    let base_name = tree.base_name.as_str();
+    let base_lower = base_name.to_lowercase();
+
+    // Walking into another tree (a statement's expressions) needs that
+    // tree's walker too.
+    let mut supertraits: Vec<String> = Vec::new();
+    for t in &tree.types {
+        for f in &t.fields {
+            if let Some((child, _)) = walked_tree(&f.ftype, tree_names) {
+                let supertrait = format!("{child}Walker<E>");
+                if child != base_name && !supertraits.contains(&supertrait) {
+                    supertraits.push(supertrait);
+                }
+            }
+        }
+    }
+    let bounds = if supertraits.is_empty() {
+        String::new()
+    } else {
+        format!(": {}", supertraits.join(" + "))
+    };
+
+    // Only the visitor tests walk trees so far.
+    writeln!(file, "#[allow(dead_code)]")?;
+    writeln!(
+        file,
+        "pub trait {base_name}Walker<E = JialoxError>{bounds} {{"
+    )?;
+    writeln!(
+        file,
+        "    fn walk_{base_lower}(&mut self, {base_lower}: &{base_name}) -> Result<(), E> {{"
+    )?;
+    writeln!(file, "        match {base_lower} {{")?;
+    for t in &tree.types {
+        writeln!(
+            file,
+            "            {base_name}::{}(v) => self.walk_{}_{base_lower}(v),",
+            t.base_class_name,
+            to_snake_case(&t.base_class_name)
+        )?;
+    }
+    writeln!(file, "        }}")?;
+    writeln!(file, "    }}")?;
+    for t in &tree.types {
+        let node = to_snake_case(&t.base_class_name);
+        writeln!(file)?;
+        writeln!(
+            file,
+            "    fn walk_{node}_{base_lower}(&mut self, {base_lower}: &{}) -> Result<(), E> {{",
+            t.class_name
+        )?;
+        writeln!(
+            file,
+            "        walk_{node}_{base_lower}_children(self, {base_lower})"
+        )?;
+        writeln!(file, "    }}")?;
+    }
+    writeln!(file, "}}\n")?;
+
+    for t in &tree.types {
+        let node = to_snake_case(&t.base_class_name);
+        let children: Vec<(&Field, &str, &str)> = t
+            .fields
+            .iter()
+            .filter_map(|f| walked_tree(&f.ftype, tree_names).map(|(c, k)| (f, c, k)))
+            .collect();
+        let param = if children.is_empty() {
+            format!("_{base_lower}")
+        } else {
+            base_lower.clone()
+        };
+        let walker = if children.is_empty() {
+            "_walker"
+        } else {
+            "walker"
+        };
+        writeln!(file, "#[allow(dead_code)]")?;
         writeln!(
             file,
-            "    fn visit_{0}_{1}(&self, {1}: &{2}) -> Result<T, JialoxError>;",
-            t.base_class_name.to_lowercase(),
-            base_name.to_lowercase(),
+            "pub fn walk_{node}_{base_lower}_children<W, E>({walker}: &mut W, {param}: &{}) -> Result<(), E>",
             t.class_name
         )?;
+        writeln!(file, "where")?;
+        writeln!(file, "    W: {base_name}Walker<E> + ?Sized,")?;
+        writeln!(file, "{{")?;
+        for (f, child, kind) in children {
+            let walk = format!("walk_{}", child.to_lowercase());
+            match kind {
+                "option" => writeln!(
+                    file,
+                    "    if let Some(v) = &{base_lower}.{} {{\n        walker.{walk}(v)?;\n    }}",
+                    f.name
+                )?,
+                "vec" => writeln!(
+                    file,
+                    "    for v in &{base_lower}.{} {{\n        walker.{walk}(v)?;\n    }}",
+                    f.name
+                )?,
+                _ => writeln!(file, "    walker.{walk}(&{base_lower}.{})?;", f.name)?,
+            }
+        }
+        writeln!(file, "    Ok(())")?;
+        writeln!(file, "}}\n")?;
+    }
+    Ok(())
+}
+
+/// Which tree a field holds, and how: `Rc<Expr>` is `("Expr", "one")`,
+/// `Option<Rc<Expr>>` is `("Expr", "option")`, `Vec<Rc<Stmt>>` is
+/// `("Stmt", "vec")`. Fields holding no tree node give `None`.
+fn walked_tree<'a>(ftype: &str, tree_names: &[&'a str]) -> Option<(&'a str, &'static str)> {
+    let (inner, kind) = if let Some(inner) = ftype.strip_prefix("Option<") {
+        (inner.strip_suffix('>')?, "option")
+    } else if let Some(inner) = ftype.strip_prefix("Vec<") {
+        (inner.strip_suffix('>')?, "vec")
+    } else {
+        (ftype, "one")
+    };
+    let name = inner.strip_prefix("Rc<")?.strip_suffix('>')?;
+    tree_names.iter().find(|t| **t == name).map(|t| (*t, kind))
+}
+
+/// `CompoundAssign` -> `compound_assign`, for visitor method names.
+fn to_snake_case(name: &str) -> String {
+    let mut snake = String::new();
+    for (i, ch) in name.chars().enumerate() {
+        if ch.is_ascii_uppercase() {
+            if i > 0 {
+                snake.push('_');
+            }
+            snake.push(ch.to_ascii_lowercase());
+        } else {
+            snake.push(ch);
+        }
+    }
+    snake
+}
+
+/// Emits `arena.rs`: an `<Base>Id` and `<Base>Node` per tree, the `Ast`
+/// arena that owns every node, and the `AstBuilder` trait the parser builds
+/// through, implemented both for `Ast` and for `RcBuilder` (the `Rc` trees).
+fn define_arena(
+    spec_path: &str,
+    output_dir: &str,
+    trees: &[TreeDef],
+    tree_names: &[&str],
+) -> io::Result<()> {
+    let mut file = File::create(format!("{output_dir}/arena.rs"))?;
+
+    writeln!(
+        file,
+        "// Generated by build.rs from {spec_path}. Do not edit.\n"
+    )?;
+    writeln!(file, "use std::rc::Rc;")?;
+    let mut modules: Vec<String> = Vec::new();
+    for tree in trees {
+        let own = tree.base_name.to_lowercase();
+        for m in tree.imports.iter().chain(std::iter::once(&own)) {
+            if m != "rc" && m != "error" && !modules.contains(m) {
+                modules.push(m.clone());
+            }
+        }
+    }
+    for m in &modules {
+        writeln!(file, "use crate::{m}::*;")?;
+    }
+
+    // Field types with every tree reference replaced by `{}`, filled in
+    // with an id type for the arena and `Self::<Base>` for the builder.
+    let shape = |ftype: &str| -> String {
+        match walked_tree(ftype, tree_names) {
+            Some((_, "option")) => "Option<{}>".to_string(),
+            Some((_, "vec")) => "Vec<{}>".to_string(),
+            Some(_) => "{}".to_string(),
+            None => ftype.to_string(),
+        }
+    };
+    let arena_type = |ftype: &str| match walked_tree(ftype, tree_names) {
+        Some((child, _)) => shape(ftype).replace("{}", &format!("{child}Id")),
+        None => ftype.to_string(),
+    };
+    let builder_type = |ftype: &str| match walked_tree(ftype, tree_names) {
+        Some((child, _)) => shape(ftype).replace("{}", &format!("Self::{child}")),
+        None => ftype.to_string(),
+    };
+
+    for tree in trees {
+        let base = &tree.base_name;
+        writeln!(file)?;
+        writeln!(
+            file,
+            "#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]"
+        )?;
+        writeln!(file, "pub struct {base}Id(u32);\n")?;
+        writeln!(file, "impl {base}Id {{")?;
+        writeln!(file, "    pub fn index(self) -> usize {{")?;
+        writeln!(file, "        self.0 as usize")?;
+        writeln!(file, "    }}")?;
+        writeln!(file, "}}\n")?;
+
+        // Tokens kept for error positions go unread by the arena's users.
+        writeln!(file, "#[derive(Debug)]")?;
+        writeln!(file, "#[allow(dead_code)]")?;
+        writeln!(file, "pub enum {base}Node {{")?;
+        for t in &tree.types {
+            let fields: Vec<String> = t
+                .fields
+                .iter()
+                .map(|f| format!("{}: {}", f.name, arena_type(&f.ftype)))
+                .collect();
+            writeln!(
+                file,
+                "    {} {{ {} }},",
+                t.base_class_name,
+                fields.join(", ")
+            )?;
+        }
+        writeln!(file, "}}")?;
+    }
+
+    writeln!(
+        file,
+        "\n/// Owns every node of a parsed program; nodes refer to each other by id."
+    )?;
+    writeln!(file, "#[derive(Debug, Default)]")?;
+    writeln!(file, "pub struct Ast {{")?;
+    for tree in trees {
+        let base = &tree.base_name;
+        writeln!(file, "    {}s: Vec<{base}Node>,", base.to_lowercase())?;
+    }
+    writeln!(file, "}}\n")?;
+    writeln!(file, "impl Ast {{")?;
+    writeln!(file, "    pub fn new() -> Ast {{")?;
+    writeln!(file, "        Ast::default()")?;
+    writeln!(file, "    }}")?;
+    for tree in trees {
+        let base = &tree.base_name;
+        let lower = base.to_lowercase();
+        writeln!(file)?;
+        writeln!(
+            file,
+            "    pub fn {lower}(&self, id: {base}Id) -> &{base}Node {{"
+        )?;
+        writeln!(file, "        &self.{lower}s[id.index()]")?;
+        writeln!(file, "    }}\n")?;
+        writeln!(file, "    #[allow(dead_code)]")?;
+        writeln!(file, "    pub fn {lower}_count(&self) -> usize {{")?;
+        writeln!(file, "        self.{lower}s.len()")?;
+        writeln!(file, "    }}\n")?;
+        writeln!(
+            file,
+            "    pub fn add_{lower}(&mut self, node: {base}Node) -> {base}Id {{"
+        )?;
+        writeln!(file, "        self.{lower}s.push(node);")?;
+        writeln!(file, "        {base}Id(self.{lower}s.len() as u32 - 1)")?;
+        writeln!(file, "    }}")?;
+    }
+    writeln!(file, "}}\n")?;
+
+    writeln!(file, "/// What the parser builds syntax trees with.")?;
+    writeln!(file, "pub trait AstBuilder {{")?;
+    for tree in trees {
+        writeln!(file, "    type {};", tree.base_name)?;
+    }
+    for tree in trees {
+        for t in &tree.types {
+            let params: Vec<String> = t
+                .fields
+                .iter()
+                .map(|f| format!("{}: {}", f.name, builder_type(&f.ftype)))
+                .collect();
+            writeln!(
+                file,
+                "    fn {}_{}(&mut self, {}) -> Self::{};",
+                to_snake_case(&t.base_class_name),
+                tree.base_name.to_lowercase(),
+                params.join(", "),
+                tree.base_name
+            )?;
+        }
     }
     writeln!(file, "}}\n")?;
 
+    writeln!(file, "/// Builds the `Rc` trees the interpreter walks.")?;
+    writeln!(file, "pub struct RcBuilder;\n")?;
+    for (builder, is_arena) in [("RcBuilder", false), ("Ast", true)] {
+        writeln!(file, "impl AstBuilder for {builder} {{")?;
+        for tree in trees {
+            let base = &tree.base_name;
+            if is_arena {
+                writeln!(file, "    type {base} = {base}Id;")?;
+            } else {
+                writeln!(file, "    type {base} = Rc<{base}>;")?;
+            }
+        }
+        for tree in trees {
+            let base = &tree.base_name;
+            for t in &tree.types {
+                let params: Vec<String> = t
+                    .fields
+                    .iter()
+                    .map(|f| format!("{}: {}", f.name, builder_type(&f.ftype)))
+                    .collect();
+                let names: Vec<&str> = t.fields.iter().map(|f| f.name.as_str()).collect();
+                writeln!(file)?;
+                writeln!(
+                    file,
+                    "    fn {}_{}(&mut self, {}) -> Self::{base} {{",
+                    to_snake_case(&t.base_class_name),
+                    base.to_lowercase(),
+                    params.join(", ")
+                )?;
+                if is_arena {
+                    writeln!(
+                        file,
+                        "        self.add_{}({base}Node::{} {{ {} }})",
+                        base.to_lowercase(),
+                        t.base_class_name,
+                        names.join(", ")
+                    )?;
+                } else {
+                    writeln!(
+                        file,
+                        "        Rc::new({base}::{}({}))",
+                        constructor_name(&t.base_class_name),
+                        names.join(", ")
+                    )?;
+                }
+                writeln!(file, "    }}")?;
+            }
+        }
+        writeln!(file, "}}\n")?;
+    }
+
     Ok(())
 }
+
+/// `Literal` -> `literal`, `If` -> `r#if`, for the per-node constructors.
+fn constructor_name(node_name: &str) -> String {
+    let name = to_snake_case(node_name);
+    match name.as_str() {
+        "if" | "while" | "for" | "return" | "break" | "continue" | "loop" | "match" | "fn"
+        | "struct" | "enum" | "impl" | "trait" | "type" | "use" | "mod" => format!("r#{name}"),
+        _ => name,
+    }
+}
+
+/// The statement writing one space-separated field of a node's `Display`. Tokens print as
+/// their lexeme and empty options as `nil`; everything else uses its own
+/// `Display`.
+fn display_field(ftype: &str, name: &str) -> String {
+    if ftype == "Token" {
+        format!("write!(f, \" {{}}\", self.{name}.lexeme())?;")
+    } else if ftype.starts_with("Vec<") {
+        format!(
+            "write!(f, \" [\")?; for (i, v) in self.{name}.iter().enumerate() {{ if i > 0 {{ write!(f, \" \")?; }} write!(f, \"{{v}}\")?; }} write!(f, \"]\")?;"
+        )
+    } else if ftype.starts_with("Option<") {
+        format!(
+            "match &self.{name} {{ Some(v) => write!(f, \" {{v}}\")?, None => write!(f, \" nil\")? }}"
+        )
+    } else {
+        format!("write!(f, \" {{}}\", self.{name})?;")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spec_error(spec: &str) -> String {
+        parse_spec("test.def", spec).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn test_node_without_colon() {
+        assert_eq!(
+            spec_error("tree Expr uses token\n    Variable Token name\n"),
+            "test.def:2: expected '<Node> : <fields>', found 'Variable Token name'"
+        );
+    }
+
+    #[test]
+    fn test_unknown_field_type() {
+        assert_eq!(
+            spec_error("tree Expr uses token\n    Variable : Tokn name\n"),
+            "test.def:2: unknown type 'Tokn' for field 'name' of 'Variable'"
+        );
+        // Trees can only refer to themselves and trees defined above them.
+        assert_eq!(
+            spec_error("tree Expr uses rc\n    Block : Vec<Rc<Stmt>> body\n"),
+            "test.def:2: unknown type 'Vec<Rc<Stmt>>' for field 'body' of 'Block'"
+        );
+        assert_eq!(
+            spec_error("tree Expr uses rc\n    Call : Option<Vec<Rc<Expr>>> arguments\n"),
+            "test.def:2: unknown type 'Option<Vec<Rc<Expr>>>' for field 'arguments' of 'Call'"
+        );
+    }
+
+    #[test]
+    fn test_duplicate_field() {
+        assert_eq!(
+            spec_error("tree Expr uses token, rc\n\n    Binary : Rc<Expr> left, Token operator, Rc<Expr> left\n"),
+            "test.def:3: field 'left' is defined twice in 'Binary'"
+        );
+    }
+
+    #[test]
+    fn test_valid_spec() {
+        let trees = parse_spec(
+            "test.def",
+            "# comment\ntree Expr uses token, rc\n    Call : Rc<Expr> callee, Vec<Rc<Expr>> arguments\n",
+        )
+        .unwrap();
+        assert_eq!(trees[0].types[0].class_name, "CallExpr");
+        assert_eq!(trees[0].types[0].fields[1].ftype, "Vec<Rc<Expr>>");
+    }
+
+    #[test]
+    fn test_generated_files_name_their_spec() {
+        let dir = std::env::temp_dir().join(format!("jialox-ast-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        generate_ast("grammar/ast.def", dir.to_str().unwrap()).unwrap();
+        for file in ["expr.rs", "stmt.rs", "arena.rs"] {
+            let source = fs::read_to_string(dir.join(file)).unwrap();
+            assert!(source.starts_with("// Generated by build.rs from grammar/ast.def."));
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
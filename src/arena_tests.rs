@@ -0,0 +1,85 @@
+//! Tests for parsing into the `Ast` arena generated into `arena`.
+
+use std::collections::HashMap;
+
+use crate::arena::*;
+use crate::parser::*;
+use crate::scanner::*;
+
+fn parse_arena(source: &str) -> (Vec<StmtId>, Ast) {
+    let mut scanner = Scanner::new(source.to_string());
+    let tokens = scanner.scan_tokens().unwrap();
+    let mut parser = Parser::with_builder(tokens, Ast::new());
+    let statements = parser.parse().unwrap();
+    (statements, parser.into_builder())
+}
+
+#[test]
+fn test_parse_into_arena() {
+    let (statements, ast) = parse_arena("var a = 1 + 2 * 3;\nprint a;");
+    assert_eq!(statements.len(), 2);
+    assert_eq!(ast.stmt_count(), 2);
+
+    let initializer = match ast.stmt(statements[0]) {
+        StmtNode::Var {
+            name, initializer, ..
+        } => {
+            assert_eq!(name.lexeme(), "a");
+            initializer.unwrap()
+        }
+        node => panic!("expected a var statement, found {node:?}"),
+    };
+    match ast.expr(initializer) {
+        ExprNode::Binary {
+            operator, right, ..
+        } => {
+            assert_eq!(operator.lexeme(), "+");
+            assert!(matches!(ast.expr(*right), ExprNode::Binary { .. }));
+        }
+        node => panic!("expected a binary expression, found {node:?}"),
+    }
+    match ast.stmt(statements[1]) {
+        StmtNode::Print { expression } => {
+            assert!(matches!(ast.expr(*expression), ExprNode::Variable { .. }))
+        }
+        node => panic!("expected a print statement, found {node:?}"),
+    }
+}
+
+#[test]
+fn test_assignment_targets_in_arena() {
+    let (statements, ast) = parse_arena("x += 1;");
+    match ast.stmt(statements[0]) {
+        StmtNode::Expression { expression } => match ast.expr(*expression) {
+            ExprNode::CompoundAssign { name, .. } => assert_eq!(name.lexeme(), "x"),
+            node => panic!("expected a compound assignment, found {node:?}"),
+        },
+        node => panic!("expected an expression statement, found {node:?}"),
+    }
+
+    let mut scanner = Scanner::new("1 = 2;".to_string());
+    let tokens = scanner.scan_tokens().unwrap();
+    assert!(Parser::with_builder(tokens, Ast::new()).parse().is_err());
+}
+
+#[test]
+fn test_side_table_keyed_by_id() {
+    let (statements, ast) = parse_arena("print 1 + 2;");
+    let expression = match ast.stmt(statements[0]) {
+        StmtNode::Print { expression } => *expression,
+        node => panic!("expected a print statement, found {node:?}"),
+    };
+    let (left, right) = match ast.expr(expression) {
+        ExprNode::Binary { left, right, .. } => (*left, *right),
+        node => panic!("expected a binary expression, found {node:?}"),
+    };
+    let mut names = HashMap::new();
+    names.insert(expression, "sum");
+    names.insert(left, "left");
+    names.insert(right, "right");
+    assert_eq!(names[&left], "left");
+    assert_eq!(names[&expression], "sum");
+    // Children are added before their parents.
+    assert!(left < expression && right < expression);
+    assert_eq!(expression.index(), ast.expr_count() - 1);
+}
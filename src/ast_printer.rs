@@ -2,43 +2,374 @@ use std::rc::Rc;
 
 use crate::error::*;
 use crate::expr::*;
+use crate::literal::*;
+use crate::stmt::*;
+use crate::token::*;
 
-pub struct AstPrinter;
+/// How `AstPrinter` lays out a tree.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PrintMode {
+    /// Parenthesised prefix notation: `(* (group (+ 1 2)) 3)`.
+    Lisp,
+    /// Reverse Polish notation: `1 2 + 3 *`.
+    Rpn,
+    /// One node per line, children indented under their parent.
+    Tree,
+}
+
+impl PrintMode {
+    pub fn from_name(name: &str) -> Option<PrintMode> {
+        match name {
+            "lisp" => Some(PrintMode::Lisp),
+            "rpn" => Some(PrintMode::Rpn),
+            "tree" => Some(PrintMode::Tree),
+            _ => None,
+        }
+    }
+}
+
+pub struct AstPrinter {
+    mode: PrintMode,
+}
 
 impl AstPrinter {
-    pub fn print(&self, expr: &Expr) -> Result<String, JialoxError> {
-        expr.accept(self)
+    pub fn new(mode: PrintMode) -> AstPrinter {
+        AstPrinter { mode }
+    }
+
+    pub fn print_stmt(&self, stmt: &Stmt) -> Result<String, JialoxError> {
+        stmt.accept(self)
+    }
+
+    pub fn print_program(&self, statements: &[Rc<Stmt>]) -> Result<String, JialoxError> {
+        let mut lines = Vec::new();
+        for stmt in statements {
+            lines.push(self.print_stmt(stmt)?);
+        }
+        Ok(lines.join("\n"))
     }
 
-    fn parenthesize(&self, name: &String, exprs: &[&Rc<Expr>]) -> Result<String, JialoxError> {
-        let mut builder = format!("({name}");
+    /// Lays out one node. `name` heads a Lisp list, trails the operands in
+    /// RPN, and labels the line in tree mode, where `token` adds its line.
+    fn node(
+        &self,
+        name: &str,
+        token: Option<&Token>,
+        children: &[&Rc<Expr>],
+    ) -> Result<String, JialoxError> {
+        let mut parts = Vec::new();
+        for child in children {
+            parts.push(child.accept(self)?);
+        }
+        Ok(self.layout(name, token, parts))
+    }
+
+    fn layout(&self, name: &str, token: Option<&Token>, parts: Vec<String>) -> String {
+        match self.mode {
+            PrintMode::Lisp => {
+                let mut builder = format!("({name}");
+                for part in parts {
+                    builder = format!("{builder} {part}");
+                }
+                format!("{builder})")
+            }
+            PrintMode::Rpn => {
+                let mut parts = parts;
+                parts.push(name.to_string());
+                parts.join(" ")
+            }
+            PrintMode::Tree => {
+                let mut builder = match token {
+                    Some(token) => format!("{name} [line {}]", token.line()),
+                    None => name.to_string(),
+                };
+                for part in parts {
+                    for line in part.lines() {
+                        builder = format!("{builder}\n  {line}");
+                    }
+                }
+                builder
+            }
+        }
+    }
 
-        for expr in exprs {
-            builder = format!("{builder} {}", expr.accept(self)?);
+    fn literal(&self, value: &Option<Literal>) -> String {
+        match (value, self.mode) {
+            (Some(Literal::Str(s)), PrintMode::Tree) => format!("Literal \"{s}\""),
+            (Some(value), PrintMode::Tree) => format!("Literal {value}"),
+            (None, PrintMode::Tree) => "Literal nil".to_string(),
+            (Some(value), _) => value.to_string(),
+            (None, _) => "nil".to_string(),
         }
-        builder = format!("{builder})");
-        Ok(builder)
+    }
+}
+
+/// The leftmost token of an expression, which gives the line of nodes
+/// that keep no token of their own.
+fn first_token(expr: &Expr) -> &Token {
+    match expr {
+        Expr::Assign(a) => &a.name,
+        Expr::Binary(b) => first_token(&b.left),
+        Expr::Call(c) => first_token(&c.callee),
+        Expr::CompoundAssign(a) => &a.name,
+        Expr::Conditional(c) => first_token(&c.condition),
+        Expr::Grouping(g) => first_token(&g.expression),
+        Expr::Literal(l) => &l.token,
+        Expr::Unary(u) => &u.operator,
+        Expr::Update(u) if u.prefix => &u.operator,
+        Expr::Update(u) => &u.name,
+        Expr::Variable(v) => &v.name,
     }
 }
 
 impl ExprVisitor<String> for AstPrinter {
+    fn visit_assign_expr(&self, expr: &AssignExpr) -> Result<String, JialoxError> {
+        let value = expr.value.accept(self)?;
+        let parts = vec![expr.name.lexeme(), value];
+        match self.mode {
+            PrintMode::Tree => Ok(self.layout(
+                &format!("Assign {}", expr.name.lexeme()),
+                Some(&expr.name),
+                parts[1..].to_vec(),
+            )),
+            _ => Ok(self.layout("=", None, parts)),
+        }
+    }
+
     fn visit_binary_expr(&self, expr: &BinaryExpr) -> Result<String, JialoxError> {
-        self.parenthesize(&expr.operator.as_string(), &[&expr.left, &expr.right])
+        let name = match self.mode {
+            PrintMode::Tree => format!("Binary {}", expr.operator.lexeme()),
+            _ => expr.operator.lexeme(),
+        };
+        self.node(&name, Some(&expr.operator), &[&expr.left, &expr.right])
+    }
+
+    fn visit_call_expr(&self, expr: &CallExpr) -> Result<String, JialoxError> {
+        let name = match self.mode {
+            PrintMode::Tree => "Call",
+            _ => "call",
+        };
+        let mut children = vec![&expr.callee];
+        children.extend(&expr.arguments);
+        self.node(name, Some(&expr.paren), &children)
+    }
+
+    fn visit_compound_assign_expr(&self, expr: &CompoundAssignExpr) -> Result<String, JialoxError> {
+        let value = expr.value.accept(self)?;
+        match self.mode {
+            PrintMode::Tree => Ok(self.layout(
+                &format!(
+                    "CompoundAssign {} {}",
+                    expr.name.lexeme(),
+                    expr.operator.lexeme()
+                ),
+                Some(&expr.operator),
+                vec![value],
+            )),
+            _ => Ok(self.layout(
+                &expr.operator.lexeme(),
+                None,
+                vec![expr.name.lexeme(), value],
+            )),
+        }
+    }
+
+    fn visit_conditional_expr(&self, expr: &ConditionalExpr) -> Result<String, JialoxError> {
+        let name = match self.mode {
+            PrintMode::Tree => "Conditional",
+            _ => "?:",
+        };
+        self.node(
+            name,
+            Some(first_token(&expr.condition)),
+            &[&expr.condition, &expr.then_branch, &expr.else_branch],
+        )
     }
 
     fn visit_grouping_expr(&self, expr: &GroupingExpr) -> Result<String, JialoxError> {
-        self.parenthesize(&"group".to_string(), &[&expr.expression])
+        match self.mode {
+            // Postfix notation needs no parentheses.
+            PrintMode::Rpn => expr.expression.accept(self),
+            PrintMode::Tree => self.node(
+                "Grouping",
+                Some(first_token(&expr.expression)),
+                &[&expr.expression],
+            ),
+            PrintMode::Lisp => self.node("group", None, &[&expr.expression]),
+        }
     }
 
     fn visit_literal_expr(&self, expr: &LiteralExpr) -> Result<String, JialoxError> {
-        if let Some(value) = &expr.value {
-            Ok(value.to_string())
-        } else {
-            Ok("nil".to_string())
+        let text = self.literal(&expr.value);
+        match self.mode {
+            PrintMode::Tree => Ok(self.layout(&text, Some(&expr.token), Vec::new())),
+            _ => Ok(text),
         }
     }
 
     fn visit_unary_expr(&self, expr: &UnaryExpr) -> Result<String, JialoxError> {
-        self.parenthesize(&expr.operator.as_string(), &[&expr.right])
+        let name = match self.mode {
+            PrintMode::Tree => format!("Unary {}", expr.operator.lexeme()),
+            // Keep negation apart from binary subtraction.
+            PrintMode::Rpn if expr.operator.lexeme() == "-" => "neg".to_string(),
+            _ => expr.operator.lexeme(),
+        };
+        self.node(&name, Some(&expr.operator), &[&expr.right])
+    }
+
+    fn visit_update_expr(&self, expr: &UpdateExpr) -> Result<String, JialoxError> {
+        let fixity = if expr.prefix { "pre" } else { "post" };
+        match self.mode {
+            PrintMode::Tree => Ok(self.layout(
+                &format!(
+                    "Update {}{} {}",
+                    fixity,
+                    expr.operator.lexeme(),
+                    expr.name.lexeme()
+                ),
+                Some(&expr.operator),
+                Vec::new(),
+            )),
+            _ => Ok(self.layout(
+                &format!("{}{}", fixity, expr.operator.lexeme()),
+                None,
+                vec![expr.name.lexeme()],
+            )),
+        }
+    }
+
+    fn visit_variable_expr(&self, expr: &VariableExpr) -> Result<String, JialoxError> {
+        match self.mode {
+            PrintMode::Tree => Ok(self.layout(
+                &format!("Variable {}", expr.name.lexeme()),
+                Some(&expr.name),
+                Vec::new(),
+            )),
+            _ => Ok(expr.name.lexeme()),
+        }
+    }
+}
+
+impl StmtVisitor<String> for AstPrinter {
+    fn visit_assert_stmt(&self, stmt: &AssertStmt) -> Result<String, JialoxError> {
+        let name = match self.mode {
+            PrintMode::Tree => "Assert",
+            _ => "assert",
+        };
+        self.node(name, Some(&stmt.keyword), &[&stmt.condition])
+    }
+
+    fn visit_expression_stmt(&self, stmt: &ExpressionStmt) -> Result<String, JialoxError> {
+        let name = match self.mode {
+            PrintMode::Tree => "Expression",
+            _ => ";",
+        };
+        let token = first_token(&stmt.expression);
+        self.node(name, Some(token), &[&stmt.expression])
+    }
+
+    fn visit_print_stmt(&self, stmt: &PrintStmt) -> Result<String, JialoxError> {
+        let name = match self.mode {
+            PrintMode::Tree => "Print",
+            _ => "print",
+        };
+        let token = first_token(&stmt.expression);
+        self.node(name, Some(token), &[&stmt.expression])
+    }
+
+    fn visit_test_stmt(&self, stmt: &TestStmt) -> Result<String, JialoxError> {
+        let mut parts = Vec::new();
+        for statement in &stmt.body {
+            parts.push(statement.accept(self)?);
+        }
+        let name = match self.mode {
+            PrintMode::Tree => format!("Test {}", stmt.name.lexeme()),
+            _ => format!("test {}", stmt.name.lexeme()),
+        };
+        Ok(self.layout(&name, Some(&stmt.name), parts))
+    }
+
+    fn visit_var_stmt(&self, stmt: &VarStmt) -> Result<String, JialoxError> {
+        let mut parts = Vec::new();
+        if self.mode != PrintMode::Tree {
+            parts.push(stmt.name.lexeme());
+        }
+        if let Some(initializer) = &stmt.initializer {
+            parts.push(initializer.accept(self)?);
+        }
+        match self.mode {
+            PrintMode::Tree => Ok(self.layout(
+                &format!("Var {}", stmt.name.lexeme()),
+                Some(&stmt.name),
+                parts,
+            )),
+            _ => Ok(self.layout("var", None, parts)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::*;
+    use crate::scanner::*;
+
+    fn print(mode: PrintMode, source: &str) -> String {
+        let mut scanner = Scanner::new(source.to_string());
+        let tokens = scanner.scan_tokens().unwrap();
+        let statements = Parser::new(tokens).parse().unwrap();
+        AstPrinter::new(mode).print_program(&statements).unwrap()
+    }
+
+    #[test]
+    fn test_lisp() {
+        assert_eq!(
+            print(PrintMode::Lisp, "print -123 * (45.67);"),
+            "(print (* (- 123) (group 45.67)))"
+        );
+        assert_eq!(
+            print(PrintMode::Lisp, "var a = b ? 1 : 2;\na += c++;"),
+            "(var a (?: b 1 2))\n(; (+= a (post++ c)))"
+        );
+    }
+
+    #[test]
+    fn test_reverse_polish() {
+        assert_eq!(
+            print(PrintMode::Rpn, "(1 + 2) * (4 - 3);"),
+            "1 2 + 4 3 - * ;"
+        );
+        assert_eq!(
+            print(PrintMode::Rpn, "print -a ** 2 == ++b;"),
+            "a 2 ** neg b pre++ == print"
+        );
+        assert_eq!(print(PrintMode::Rpn, "var x;"), "x var");
+    }
+
+    #[test]
+    fn test_tree() {
+        assert_eq!(
+            print(PrintMode::Tree, "var a = 1 +\n  -b;\nprint \"hi\";"),
+            "Var a [line 1]\n  \
+               Binary + [line 1]\n    \
+                 Literal 1 [line 1]\n    \
+                 Unary - [line 2]\n      \
+                   Variable b [line 2]\n\
+             Print [line 3]\n  \
+               Literal \"hi\" [line 3]"
+        );
+    }
+
+    #[test]
+    fn test_tree_lines_of_tokenless_nodes() {
+        assert_eq!(
+            print(PrintMode::Tree, "\n(a)\n  ? 1\n  : 2;"),
+            "Expression [line 2]\n  \
+               Conditional [line 2]\n    \
+                 Grouping [line 2]\n      \
+                   Variable a [line 2]\n    \
+                 Literal 1 [line 3]\n    \
+                 Literal 2 [line 4]"
+        );
     }
 }
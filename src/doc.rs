@@ -0,0 +1,137 @@
+use crate::arena::*;
+use crate::error::*;
+use crate::formatter::*;
+use crate::scanner::*;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DocFormat {
+    Markdown,
+    Html,
+}
+
+/// A top-level declaration and the doc comment written above it.
+struct Entry {
+    name: String,
+    declaration: String,
+    doc: Option<String>,
+}
+
+/// API documentation for a set of scripts, given as `(file name, source)`
+/// pairs: one section per file listing its top-level declarations. Only
+/// variables can be declared so far.
+pub fn document(files: &[(String, String)], format: DocFormat) -> Result<String, JialoxError> {
+    let mut sections = Vec::new();
+    for (name, source) in files {
+        sections.push((name, entries(source)?));
+    }
+
+    let mut output = String::new();
+    match format {
+        DocFormat::Markdown => {
+            for (name, entries) in sections {
+                output.push_str(&format!("# {name}\n"));
+                for entry in entries {
+                    output.push_str(&format!("\n## `{}`\n\n", entry.name));
+                    output.push_str(&format!("```jialox\n{}\n```\n", entry.declaration));
+                    if let Some(doc) = entry.doc {
+                        output.push_str(&format!("\n{doc}\n"));
+                    }
+                }
+            }
+        }
+        DocFormat::Html => {
+            output.push_str("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n");
+            output.push_str("<title>Jialox API</title>\n</head>\n<body>\n");
+            for (name, entries) in sections {
+                output.push_str(&format!("<h1>{}</h1>\n", escape_html(name)));
+                for entry in entries {
+                    let name = escape_html(&entry.name);
+                    output.push_str(&format!("<h2 id=\"{name}\"><code>{name}</code></h2>\n"));
+                    output.push_str(&format!(
+                        "<pre><code>{}</code></pre>\n",
+                        escape_html(&entry.declaration)
+                    ));
+                    for paragraph in entry.doc.iter().flat_map(|doc| doc.split("\n\n")) {
+                        output.push_str(&format!("<p>{}</p>\n", escape_html(paragraph)));
+                    }
+                }
+            }
+            output.push_str("</body>\n</html>\n");
+        }
+    }
+    Ok(output)
+}
+
+fn entries(source: &str) -> Result<Vec<Entry>, JialoxError> {
+    let mut scanner = Scanner::new(source.to_string());
+    let tokens = scanner.scan_tokens()?;
+    let layout = Layout::parse(tokens)?;
+    Ok(layout
+        .statements()
+        .iter()
+        .filter_map(|&stmt| match layout.ast().stmt(stmt) {
+            StmtNode::Var { name, doc, .. } => Some(Entry {
+                name: name.lexeme(),
+                declaration: layout.format_statement(stmt),
+                doc: doc.clone(),
+            }),
+            _ => None,
+        })
+        .collect())
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LIBRARY: &str = "\
+/// Seconds in a minute.
+var minute = 60;
+
+/**
+ * Seconds in an hour.
+ *
+ * Handy for <timers>.
+ */
+var hour = minute * 60;
+
+print hour;
+var undocumented;
+";
+
+    fn document_library(format: DocFormat) -> String {
+        document(&[("time.jlx".to_string(), LIBRARY.to_string())], format).unwrap()
+    }
+
+    #[test]
+    fn test_markdown() {
+        assert_eq!(
+            document_library(DocFormat::Markdown),
+            "# time.jlx\n\n\
+             ## `minute`\n\n```jialox\nvar minute = 60;\n```\n\nSeconds in a minute.\n\n\
+             ## `hour`\n\n```jialox\nvar hour = minute * 60;\n```\n\n\
+             Seconds in an hour.\n\nHandy for <timers>.\n\n\
+             ## `undocumented`\n\n```jialox\nvar undocumented;\n```\n"
+        );
+    }
+
+    #[test]
+    fn test_html() {
+        let html = document_library(DocFormat::Html);
+        assert!(html.contains("<h1>time.jlx</h1>\n"));
+        assert!(html.contains(
+            "<h2 id=\"hour\"><code>hour</code></h2>\n\
+             <pre><code>var hour = minute * 60;</code></pre>\n\
+             <p>Seconds in an hour.</p>\n\
+             <p>Handy for &lt;timers&gt;.</p>\n"
+        ));
+        assert!(html.ends_with("</body>\n</html>\n"));
+    }
+}
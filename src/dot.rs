@@ -0,0 +1,215 @@
+use std::rc::Rc;
+
+use crate::error::*;
+use crate::expr::*;
+use crate::literal::*;
+use crate::stmt::*;
+
+/// Renders syntax trees as a Graphviz digraph, one box per node with edges
+/// to its children in source order. Each visit returns the name of the node
+/// it drew.
+pub struct DotPrinter {
+    lines: Vec<String>,
+    next_id: usize,
+}
+
+impl DotPrinter {
+    pub fn new() -> DotPrinter {
+        DotPrinter {
+            lines: Vec::new(),
+            next_id: 0,
+        }
+    }
+
+    pub fn print_program(mut self, statements: &[Rc<Stmt>]) -> Result<String, JialoxError> {
+        let program = self.node("program", &[]);
+        for stmt in statements {
+            let child = stmt.accept_mut(&mut self)?;
+            self.edge(&program, &child);
+        }
+
+        let mut dot = String::from("digraph ast {\n");
+        dot.push_str("    graph [ordering=out];\n");
+        dot.push_str("    node [shape=box, fontname=\"monospace\"];\n");
+        for line in &self.lines {
+            dot.push_str(&format!("    {line}\n"));
+        }
+        dot.push_str("}\n");
+        Ok(dot)
+    }
+
+    /// Draws a node labelled `label` with edges to the already drawn
+    /// `children`.
+    fn node(&mut self, label: &str, children: &[String]) -> String {
+        let name = format!("n{}", self.next_id);
+        self.next_id += 1;
+        self.lines
+            .push(format!("{name} [label=\"{}\"];", escape(label)));
+        for child in children {
+            self.edge(&name, child);
+        }
+        name
+    }
+
+    fn edge(&mut self, from: &str, to: &str) {
+        self.lines.push(format!("{from} -> {to};"));
+    }
+}
+
+fn escape(label: &str) -> String {
+    label
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+impl ExprVisitorMut<String> for DotPrinter {
+    fn visit_assign_expr(&mut self, expr: &AssignExpr) -> Result<String, JialoxError> {
+        let value = expr.value.accept_mut(self)?;
+        Ok(self.node(&format!("{} =", expr.name.lexeme()), &[value]))
+    }
+
+    fn visit_binary_expr(&mut self, expr: &BinaryExpr) -> Result<String, JialoxError> {
+        let left = expr.left.accept_mut(self)?;
+        let right = expr.right.accept_mut(self)?;
+        Ok(self.node(&expr.operator.lexeme(), &[left, right]))
+    }
+
+    fn visit_call_expr(&mut self, expr: &CallExpr) -> Result<String, JialoxError> {
+        let mut children = vec![expr.callee.accept_mut(self)?];
+        for argument in &expr.arguments {
+            children.push(argument.accept_mut(self)?);
+        }
+        Ok(self.node("call", &children))
+    }
+
+    fn visit_compound_assign_expr(
+        &mut self,
+        expr: &CompoundAssignExpr,
+    ) -> Result<String, JialoxError> {
+        let value = expr.value.accept_mut(self)?;
+        let label = format!("{} {}", expr.name.lexeme(), expr.operator.lexeme());
+        Ok(self.node(&label, &[value]))
+    }
+
+    fn visit_conditional_expr(&mut self, expr: &ConditionalExpr) -> Result<String, JialoxError> {
+        let condition = expr.condition.accept_mut(self)?;
+        let then_branch = expr.then_branch.accept_mut(self)?;
+        let else_branch = expr.else_branch.accept_mut(self)?;
+        Ok(self.node("?:", &[condition, then_branch, else_branch]))
+    }
+
+    fn visit_grouping_expr(&mut self, expr: &GroupingExpr) -> Result<String, JialoxError> {
+        let expression = expr.expression.accept_mut(self)?;
+        Ok(self.node("( )", &[expression]))
+    }
+
+    fn visit_literal_expr(&mut self, expr: &LiteralExpr) -> Result<String, JialoxError> {
+        let label = match &expr.value {
+            Some(Literal::Str(s)) => format!("\"{s}\""),
+            Some(value) => value.to_string(),
+            None => "nil".to_string(),
+        };
+        Ok(self.node(&label, &[]))
+    }
+
+    fn visit_unary_expr(&mut self, expr: &UnaryExpr) -> Result<String, JialoxError> {
+        let right = expr.right.accept_mut(self)?;
+        Ok(self.node(&expr.operator.lexeme(), &[right]))
+    }
+
+    fn visit_update_expr(&mut self, expr: &UpdateExpr) -> Result<String, JialoxError> {
+        let label = if expr.prefix {
+            format!("{}{}", expr.operator.lexeme(), expr.name.lexeme())
+        } else {
+            format!("{}{}", expr.name.lexeme(), expr.operator.lexeme())
+        };
+        Ok(self.node(&label, &[]))
+    }
+
+    fn visit_variable_expr(&mut self, expr: &VariableExpr) -> Result<String, JialoxError> {
+        Ok(self.node(&expr.name.lexeme(), &[]))
+    }
+}
+
+impl StmtVisitorMut<String> for DotPrinter {
+    fn visit_assert_stmt(&mut self, stmt: &AssertStmt) -> Result<String, JialoxError> {
+        let condition = stmt.condition.accept_mut(self)?;
+        Ok(self.node("assert", &[condition]))
+    }
+
+    fn visit_expression_stmt(&mut self, stmt: &ExpressionStmt) -> Result<String, JialoxError> {
+        let expression = stmt.expression.accept_mut(self)?;
+        Ok(self.node("expression;", &[expression]))
+    }
+
+    fn visit_print_stmt(&mut self, stmt: &PrintStmt) -> Result<String, JialoxError> {
+        let expression = stmt.expression.accept_mut(self)?;
+        Ok(self.node("print", &[expression]))
+    }
+
+    fn visit_test_stmt(&mut self, stmt: &TestStmt) -> Result<String, JialoxError> {
+        let mut children = Vec::new();
+        for statement in &stmt.body {
+            children.push(statement.accept_mut(self)?);
+        }
+        Ok(self.node(&format!("test {}", stmt.name.lexeme()), &children))
+    }
+
+    fn visit_var_stmt(&mut self, stmt: &VarStmt) -> Result<String, JialoxError> {
+        let mut children = Vec::new();
+        if let Some(initializer) = &stmt.initializer {
+            children.push(initializer.accept_mut(self)?);
+        }
+        Ok(self.node(&format!("var {}", stmt.name.lexeme()), &children))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::*;
+    use crate::scanner::*;
+
+    fn dot(source: &str) -> String {
+        let mut scanner = Scanner::new(source.to_string());
+        let tokens = scanner.scan_tokens().unwrap();
+        let statements = Parser::new(tokens).parse().unwrap();
+        DotPrinter::new().print_program(&statements).unwrap()
+    }
+
+    #[test]
+    fn test_precedence_tree() {
+        assert_eq!(
+            dot("print 1 + 2 * 3;"),
+            "digraph ast {\n    \
+               graph [ordering=out];\n    \
+               node [shape=box, fontname=\"monospace\"];\n    \
+               n0 [label=\"program\"];\n    \
+               n1 [label=\"1\"];\n    \
+               n2 [label=\"2\"];\n    \
+               n3 [label=\"3\"];\n    \
+               n4 [label=\"*\"];\n    \
+               n4 -> n2;\n    \
+               n4 -> n3;\n    \
+               n5 [label=\"+\"];\n    \
+               n5 -> n1;\n    \
+               n5 -> n4;\n    \
+               n6 [label=\"print\"];\n    \
+               n6 -> n5;\n    \
+               n0 -> n6;\n\
+             }\n"
+        );
+    }
+
+    #[test]
+    fn test_labels() {
+        let output = dot("var s = \"say \\\";\ns += a ? -b : c++;");
+        assert!(output.contains("[label=\"\\\"say \\\\\\\"\"];"));
+        assert!(output.contains("[label=\"var s\"];"));
+        assert!(output.contains("[label=\"s +=\"];"));
+        assert!(output.contains("[label=\"?:\"];"));
+        assert!(output.contains("[label=\"-\"];"));
+        assert!(output.contains("[label=\"c++\"];"));
+    }
+}
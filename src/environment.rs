@@ -0,0 +1,56 @@
+use std::collections::HashMap;
+
+use crate::error::*;
+use crate::literal::*;
+use crate::token::*;
+
+#[derive(Clone)]
+pub struct Environment {
+    values: HashMap<String, Literal>,
+}
+
+impl Environment {
+    pub fn new() -> Environment {
+        Environment {
+            values: HashMap::new(),
+        }
+    }
+
+    /// Everything defined so far, sorted by name.
+    pub fn bindings(&self) -> Vec<(String, Literal)> {
+        let mut bindings: Vec<(String, Literal)> = self
+            .values
+            .iter()
+            .map(|(name, value)| (name.clone(), value.clone()))
+            .collect();
+        bindings.sort_by(|a, b| a.0.cmp(&b.0));
+        bindings
+    }
+
+    pub fn define(&mut self, name: &str, value: Literal) {
+        self.values.insert(name.to_string(), value);
+    }
+
+    pub fn get(&self, name: &Token) -> Result<Literal, JialoxError> {
+        match self.values.get(&name.lexeme()) {
+            Some(value) => Ok(value.clone()),
+            None => Err(JialoxError::error(
+                name.line(),
+                &format!("Undefined variable '{}'.", name.lexeme()),
+            )),
+        }
+    }
+
+    pub fn assign(&mut self, name: &Token, value: Literal) -> Result<(), JialoxError> {
+        match self.values.get_mut(&name.lexeme()) {
+            Some(slot) => {
+                *slot = value;
+                Ok(())
+            }
+            None => Err(JialoxError::error(
+                name.line(),
+                &format!("Undefined variable '{}'.", name.lexeme()),
+            )),
+        }
+    }
+}
@@ -1,10 +1,42 @@
+use std::cell::{Cell, RefCell};
+
 use crate::token::*;
 use crate::token_type::*;
+
+thread_local! {
+    static QUIET: Cell<bool> = const { Cell::new(false) };
+    static CAPTURED: RefCell<Option<Vec<String>>> = const { RefCell::new(None) };
+}
+
+/// Runs `f` without printing the errors it runs into, e.g. to try parsing
+/// input that may not be finished yet.
+pub fn quietly<T>(f: impl FnOnce() -> T) -> T {
+    let was_quiet = QUIET.with(|quiet| quiet.replace(true));
+    let result = f();
+    QUIET.with(|quiet| quiet.set(was_quiet));
+    result
+}
+
+/// Runs `f` and returns the errors it reports, one line each, instead of
+/// printing them.
+#[cfg(test)]
+pub fn capture_errors<T>(f: impl FnOnce() -> T) -> (T, Vec<String>) {
+    let outer = CAPTURED.with(|captured| captured.replace(Some(Vec::new())));
+    let result = f();
+    let errors = CAPTURED.with(|captured| captured.replace(outer));
+    (result, errors.unwrap_or_default())
+}
+
 #[derive(Debug)]
 pub struct JialoxError {
-    token: Option<Token>,
+    /// Boxed so that results carrying an error stay small.
+    token: Option<Box<Token>>,
     line: usize,
     message: String,
+    /// The source ran out before the construct being read was complete.
+    at_end: bool,
+    /// Not an error at all: the program called `exit(code)`.
+    exit_code: Option<i32>,
 }
 
 impl JialoxError {
@@ -13,6 +45,34 @@ impl JialoxError {
             token: None,
             line,
             message: message.to_string(),
+            at_end: false,
+            exit_code: None,
+        };
+        err.report("");
+        err
+    }
+
+    /// Unwinds the interpreter out of a call to `exit(code)`. Nothing is
+    /// reported.
+    pub fn exit(line: usize, code: i32) -> JialoxError {
+        JialoxError {
+            token: None,
+            line,
+            message: format!("exit({code})"),
+            at_end: false,
+            exit_code: Some(code),
+        }
+    }
+
+    /// An error about a string or comment left open at the end of the
+    /// source.
+    pub fn unterminated(line: usize, message: &str) -> JialoxError {
+        let err = JialoxError {
+            token: None,
+            line,
+            message: message.to_string(),
+            at_end: true,
+            exit_code: None,
         };
         err.report("");
         err
@@ -20,22 +80,49 @@ impl JialoxError {
 
     pub fn parse_error(token: &Token, message: &str) -> JialoxError {
         let err = JialoxError {
-            token: Some(token.mirror()),
+            token: Some(Box::new(token.mirror())),
             line: token.line(),
             message: message.to_string(),
+            at_end: token.is(TokenType::Eof),
+            exit_code: None,
         };
         err.report("");
         err
     }
 
+    /// Whether more input could have fixed this error.
+    pub fn is_at_end(&self) -> bool {
+        self.at_end
+    }
+
+    /// The status the program asked to exit with, if this came from `exit()`.
+    pub fn exit_code(&self) -> Option<i32> {
+        self.exit_code
+    }
+
+    pub fn line(&self) -> usize {
+        self.line
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    /// Prints the error as `[line N] Error at 'token': message`, or hands
+    /// it to `capture_errors`.
     pub fn report(&self, loc: &str) {
-        eprintln!("[line {}] Error{}: {}", self.line, loc, self.message);
-        if let Some(token) = &self.token {
-            if token.is(TokenType::Eof) {
-                eprintln!("{} at end {}", token.line(), self.message);
-            } else {
-                eprintln!("{} at '{}' {}", token.line(), token.lexeme(), self.message);
-            }
+        if QUIET.with(Cell::get) {
+            return;
         }
+        let loc = match &self.token {
+            Some(token) if token.is(TokenType::Eof) => " at end".to_string(),
+            Some(token) => format!(" at '{}'", token.lexeme()),
+            None => loc.to_string(),
+        };
+        let text = format!("[line {}] Error{}: {}", self.line, loc, self.message);
+        CAPTURED.with(|captured| match &mut *captured.borrow_mut() {
+            Some(errors) => errors.push(text),
+            None => eprintln!("{text}"),
+        });
     }
 }
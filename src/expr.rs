@@ -0,0 +1,3 @@
+// Generated by build.rs from grammar/ast.def.
+
+include!(concat!(env!("OUT_DIR"), "/expr.rs"));
@@ -0,0 +1,509 @@
+use std::iter::Peekable;
+use std::slice;
+
+use crate::arena::*;
+use crate::error::*;
+use crate::parser::*;
+use crate::scanner::*;
+use crate::token::*;
+use crate::token_type::*;
+
+/// Statements longer than this are broken over several lines.
+const MAX_WIDTH: usize = 80;
+const INDENT: &str = "    ";
+
+/// Re-emits a program in the canonical layout: one statement per line,
+/// single spaces around binary operators, at most one blank line in a row,
+/// and over-long statements wrapped before their loosest operators.
+/// Comments are kept in place between statements, test block bodies
+/// included. One found inside a single statement moves to the end of it,
+/// as wrapping leaves it no fixed place.
+pub fn format_source(source: &str) -> Result<String, JialoxError> {
+    let mut scanner = Scanner::lossless(source.to_string());
+    let tokens = scanner.scan_tokens()?;
+    let layout = Layout::parse(tokens)?;
+    let comments = comments(tokens);
+
+    let mut output = Output {
+        tokens,
+        comments: comments.iter().peekable(),
+        text: String::new(),
+        last_line: None,
+        depth: 0,
+    };
+    let mut spans = layout.spans.iter();
+    for &stmt in &layout.statements {
+        layout.write(stmt, &mut spans, &mut output);
+    }
+    while let Some(comment) = output.comments.next() {
+        output.comment(comment);
+    }
+    Ok(output.text)
+}
+
+/// A comment found in the trivia of a lossless scan.
+struct Comment {
+    text: String,
+    line: usize,
+    end_line: usize,
+    /// Index of the token that follows the comment.
+    token: usize,
+    /// Whether the comment shares its first line with the token before it.
+    trailing: bool,
+}
+
+/// The comments among the tokens' trivia, in source order.
+fn comments(tokens: &[Token]) -> Vec<Comment> {
+    let mut comments = Vec::new();
+    let mut line = 1;
+    for (index, token) in tokens.iter().enumerate() {
+        Comment::collect(
+            token.leading_trivia(),
+            index,
+            false,
+            &mut line,
+            &mut comments,
+        );
+        // A multi-line string spans lines of its own.
+        line += token.lexeme().matches('\n').count();
+        let trailing = token.trailing_trivia();
+        Comment::collect(trailing, index + 1, true, &mut line, &mut comments);
+    }
+    comments
+}
+
+impl Comment {
+    /// Adds the comments among `trivia`, which starts on `line`, and moves
+    /// `line` past it.
+    fn collect(
+        trivia: &[Trivia],
+        token: usize,
+        trailing: bool,
+        line: &mut usize,
+        comments: &mut Vec<Comment>,
+    ) {
+        for piece in trivia {
+            let end_line = *line + piece.text.matches('\n').count();
+            if matches!(
+                piece.kind,
+                TriviaKind::LineComment | TriviaKind::BlockComment
+            ) {
+                comments.push(Comment {
+                    text: piece.text.clone(),
+                    line: *line,
+                    end_line,
+                    token,
+                    trailing,
+                });
+            }
+            *line = end_line;
+        }
+    }
+}
+
+struct Output<'a> {
+    tokens: &'a [Token],
+    /// Comments not yet written, in source order.
+    comments: Peekable<slice::Iter<'a, Comment>>,
+    text: String,
+    /// Source line the last thing written ended on.
+    last_line: Option<usize>,
+    /// How many blocks deep the lines being written are.
+    depth: usize,
+}
+
+impl Output<'_> {
+    fn lines(&mut self, line: usize, end_line: usize, lines: Vec<String>) {
+        if matches!(self.last_line, Some(last) if line > last + 1) {
+            self.text.push('\n');
+        }
+        for text in lines {
+            self.text.push_str(&INDENT.repeat(self.depth));
+            self.text.push_str(&text);
+            self.text.push('\n');
+        }
+        self.last_line = Some(end_line);
+    }
+
+    /// Writes the comments that come before the token at `index`.
+    fn comments_before(&mut self, index: usize) {
+        while let Some(comment) = self.comments.next_if(|c| c.token <= index) {
+            self.comment(comment);
+        }
+    }
+
+    /// Appends the comments inside a statement ending at the token at
+    /// `last`, and any on the same line after it.
+    fn trailing_comments(&mut self, last: usize) {
+        let mut trailing = Vec::new();
+        while let Some(comment) = self
+            .comments
+            .next_if(|c| c.token <= last || (c.token == last + 1 && c.trailing))
+        {
+            trailing.push(comment);
+        }
+        // A line comment swallows whatever follows it, so it goes last.
+        trailing.sort_by_key(|c| c.text.starts_with("//"));
+        for comment in trailing {
+            self.trailing(comment);
+        }
+    }
+
+    fn comment(&mut self, comment: &Comment) {
+        if comment.trailing && self.last_line.is_some() {
+            self.trailing(comment);
+        } else {
+            let lines = vec![comment.text.clone()];
+            self.lines(comment.line, comment.end_line, lines);
+        }
+    }
+
+    fn trailing(&mut self, comment: &Comment) {
+        self.text.pop();
+        self.text.push(' ');
+        self.text.push_str(&comment.text);
+        self.text.push('\n');
+        self.last_line = Some(comment.end_line);
+    }
+}
+
+/// A program parsed into an `Ast` arena, ready to be laid out.
+pub struct Layout {
+    ast: Ast,
+    statements: Vec<StmtId>,
+    /// First and last token of each statement, as `Parser::statement_spans`.
+    spans: Vec<(usize, usize)>,
+}
+
+impl Layout {
+    pub fn parse(tokens: &Vec<Token>) -> Result<Layout, JialoxError> {
+        let mut parser = Parser::with_builder(tokens, Ast::new());
+        let statements = parser.parse()?;
+        let spans = parser.statement_spans().to_vec();
+        Ok(Layout {
+            ast: parser.into_builder(),
+            statements,
+            spans,
+        })
+    }
+
+    pub fn ast(&self) -> &Ast {
+        &self.ast
+    }
+
+    pub fn statements(&self) -> &[StmtId] {
+        &self.statements
+    }
+
+    /// Writes a statement with its comments, taking its span and then
+    /// those of the statements in its body from `spans`.
+    fn write(&self, id: StmtId, spans: &mut slice::Iter<(usize, usize)>, output: &mut Output) {
+        let &(first, last) = spans.next().expect("a span for every statement");
+        let (line, end_line) = (output.tokens[first].line(), output.tokens[last].line());
+        output.comments_before(first);
+        match self.ast.stmt(id) {
+            StmtNode::Test { name, body } => {
+                let header = format!("test {} {{", name.lexeme());
+                output.lines(line, line, vec![header]);
+                output.depth += 1;
+                for &stmt in body {
+                    self.write(stmt, spans, output);
+                }
+                output.comments_before(last);
+                output.depth -= 1;
+                output.lines(end_line, end_line, vec!["}".to_string()]);
+            }
+            _ => output.lines(line, end_line, self.stmt(id)),
+        }
+        output.trailing_comments(last);
+    }
+
+    /// One statement in the canonical layout, without its comments.
+    pub fn format_statement(&self, id: StmtId) -> String {
+        self.stmt(id).join("\n")
+    }
+
+    fn stmt(&self, id: StmtId) -> Vec<String> {
+        let (prefix, expr) = match self.ast.stmt(id) {
+            StmtNode::Assert { condition, .. } => ("assert ".to_string(), Some(*condition)),
+            StmtNode::Test { name, body } => return self.test(name, body),
+            StmtNode::Expression { expression } => (String::new(), Some(*expression)),
+            StmtNode::Print { expression } => ("print ".to_string(), Some(*expression)),
+            StmtNode::Var {
+                name, initializer, ..
+            } => match initializer {
+                Some(initializer) => (format!("var {} = ", name.lexeme()), Some(*initializer)),
+                None => (format!("var {}", name.lexeme()), None),
+            },
+        };
+        let mut lines = match expr {
+            Some(expr) => {
+                let flat = self.flat(expr);
+                if prefix.len() + flat.len() < MAX_WIDTH {
+                    vec![flat]
+                } else {
+                    self.wrap(expr)
+                }
+            }
+            None => vec![String::new()],
+        };
+        lines[0].insert_str(0, &prefix);
+        if let Some(last) = lines.last_mut() {
+            last.push(';');
+        }
+        lines
+    }
+
+    fn test(&self, name: &Token, body: &[StmtId]) -> Vec<String> {
+        let mut lines = vec![format!("test {} {{", name.lexeme())];
+        for &stmt in body {
+            for line in self.stmt(stmt) {
+                lines.push(format!("{INDENT}{line}"));
+            }
+        }
+        lines.push("}".to_string());
+        lines
+    }
+
+    /// Breaks an expression before each operator of its loosest binary
+    /// chain, or before the `?` and `:` of a conditional. Operands stay on
+    /// one line.
+    fn wrap(&self, id: ExprId) -> Vec<String> {
+        match self.ast.expr(id) {
+            ExprNode::Binary {
+                left,
+                operator,
+                right,
+            } => {
+                let mut chain = vec![(operator, *right)];
+                let mut first = *left;
+                while let ExprNode::Binary {
+                    left,
+                    operator: inner,
+                    right,
+                } = self.ast.expr(first)
+                {
+                    if !same_precedence(inner.ttype(), operator.ttype()) {
+                        break;
+                    }
+                    chain.push((inner, *right));
+                    first = *left;
+                }
+                chain.reverse();
+
+                let mut lines = vec![self.flat(first)];
+                for (operator, right) in chain {
+                    if operator.is(TokenType::Comma) {
+                        lines.last_mut().unwrap().push(',');
+                        lines.push(format!("{INDENT}{}", self.flat(right)));
+                    } else {
+                        let operator = operator.lexeme();
+                        lines.push(format!("{INDENT}{operator} {}", self.flat(right)));
+                    }
+                }
+                lines
+            }
+            ExprNode::Conditional {
+                condition,
+                then_branch,
+                else_branch,
+            } => vec![
+                self.flat(*condition),
+                format!("{INDENT}? {}", self.flat(*then_branch)),
+                format!("{INDENT}: {}", self.flat(*else_branch)),
+            ],
+            ExprNode::Assign { name, value } => {
+                let mut lines = self.wrap(*value);
+                lines[0].insert_str(0, &format!("{} = ", name.lexeme()));
+                lines
+            }
+            ExprNode::CompoundAssign {
+                name,
+                operator,
+                value,
+            } => {
+                let mut lines = self.wrap(*value);
+                let target = format!("{} {} ", name.lexeme(), operator.lexeme());
+                lines[0].insert_str(0, &target);
+                lines
+            }
+            ExprNode::Grouping { expression } => {
+                let mut lines = self.wrap(*expression);
+                lines[0].insert(0, '(');
+                lines.last_mut().unwrap().push(')');
+                lines
+            }
+            _ => vec![self.flat(id)],
+        }
+    }
+
+    /// The expression on a single line.
+    fn flat(&self, id: ExprId) -> String {
+        match self.ast.expr(id) {
+            ExprNode::Assign { name, value } => {
+                format!("{} = {}", name.lexeme(), self.flat(*value))
+            }
+            ExprNode::Binary {
+                left,
+                operator,
+                right,
+            } if operator.is(TokenType::Comma) => {
+                format!("{}, {}", self.flat(*left), self.flat(*right))
+            }
+            ExprNode::Binary {
+                left,
+                operator,
+                right,
+            } => format!(
+                "{} {} {}",
+                self.flat(*left),
+                operator.lexeme(),
+                self.flat(*right)
+            ),
+            ExprNode::Call {
+                callee, arguments, ..
+            } => {
+                let arguments: Vec<String> = arguments.iter().map(|&a| self.flat(a)).collect();
+                format!("{}({})", self.flat(*callee), arguments.join(", "))
+            }
+            ExprNode::CompoundAssign {
+                name,
+                operator,
+                value,
+            } => format!(
+                "{} {} {}",
+                name.lexeme(),
+                operator.lexeme(),
+                self.flat(*value)
+            ),
+            ExprNode::Conditional {
+                condition,
+                then_branch,
+                else_branch,
+            } => format!(
+                "{} ? {} : {}",
+                self.flat(*condition),
+                self.flat(*then_branch),
+                self.flat(*else_branch)
+            ),
+            ExprNode::Grouping { expression } => format!("({})", self.flat(*expression)),
+            // As written, so `1.50` or a number too long for an f64 keeps
+            // its digits.
+            ExprNode::Literal { token, .. } => token.lexeme(),
+            ExprNode::Unary { operator, right } => {
+                let operator = operator.lexeme();
+                let right = self.flat(*right);
+                // `- -x` must not run together into `--x`.
+                if right.starts_with(&operator) {
+                    format!("{operator} {right}")
+                } else {
+                    format!("{operator}{right}")
+                }
+            }
+            ExprNode::Update {
+                name,
+                operator,
+                prefix: true,
+            } => format!("{}{}", operator.lexeme(), name.lexeme()),
+            ExprNode::Update { name, operator, .. } => {
+                format!("{}{}", name.lexeme(), operator.lexeme())
+            }
+            ExprNode::Variable { name } => name.lexeme(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_formats(source: &str, expected: &str) {
+        let formatted = format_source(source).unwrap();
+        assert_eq!(formatted, expected);
+        assert_eq!(
+            format_source(&formatted).unwrap(),
+            formatted,
+            "not idempotent"
+        );
+    }
+
+    #[test]
+    fn test_spacing() {
+        assert_formats(
+            "var   a=1+2*(3-4);print -a**2,a?~a:!a ;\nx+=a--;",
+            "var a = 1 + 2 * (3 - 4);\nprint -a ** 2, a ? ~a : !a;\nx += a--;\n",
+        );
+        assert_formats("print - -a;print -(--a);", "print - -a;\nprint -(--a);\n");
+    }
+
+    #[test]
+    fn test_blank_lines() {
+        assert_formats(
+            "var a;\n\n\n\nvar b; var c;\n\nprint a;\n\n",
+            "var a;\n\nvar b;\nvar c;\n\nprint a;\n",
+        );
+    }
+
+    #[test]
+    fn test_comments() {
+        assert_formats(
+            "// leading\nvar a = 1; // trailing\n\n/* block\n   comment */\nprint a /* inside */ + 1;\n// at end\n",
+            "// leading\nvar a = 1; // trailing\n\n/* block\n   comment */\nprint a + 1; /* inside */\n// at end\n",
+        );
+        // Comments inside a statement move to its end.
+        assert_formats(
+            "print 1 // one\n  + 2; /* two */",
+            "print 1 + 2; /* two */ // one\n",
+        );
+    }
+
+    #[test]
+    fn test_comments_in_test_blocks() {
+        assert_formats(
+            "test \"t\" { // header\n// first\nvar a = 1; // one\n\n  /* second */ assert a\n  == 1;\n// last\n} // after\n",
+            "test \"t\" { // header\n    // first\n    var a = 1; // one\n\n    /* second */\n    assert a == 1;\n    // last\n} // after\n",
+        );
+    }
+
+    #[test]
+    fn test_literals_keep_their_lexemes() {
+        assert_formats(
+            "print 1.50+0.1000;\nvar big=123456789012345678901234567890;",
+            "print 1.50 + 0.1000;\nvar big = 123456789012345678901234567890;\n",
+        );
+    }
+
+    #[test]
+    fn test_wrapping() {
+        let long =
+            "var total = first_value + second_value - third_value + fourth_value * 2 + fifth_value;";
+        assert_formats(
+            long,
+            "var total = first_value\n    \
+                 + second_value\n    \
+                 - third_value\n    \
+                 + fourth_value * 2\n    \
+                 + fifth_value;\n",
+        );
+        assert_formats(
+            "print condition_that_is_rather_long ? then_branch_that_is_long : else_branch_too;",
+            "print condition_that_is_rather_long\n    \
+                 ? then_branch_that_is_long\n    \
+                 : else_branch_too;\n",
+        );
+    }
+
+    #[test]
+    fn test_test_blocks() {
+        assert_formats(
+            "test \"sums\"{assert 1+1==2;assert_eq(f(1),2,\"f\");}\ntest \"empty\" {}",
+            "test \"sums\" {\n    assert 1 + 1 == 2;\n    assert_eq(f(1), 2, \"f\");\n}\ntest \"empty\" {\n}\n",
+        );
+    }
+
+    #[test]
+    fn test_idempotent_on_formatted_corpus() {
+        let source = "var a = 1;\nvar b = (a + 2) * 3; // keep\n\nprint a ? b : -b;\n";
+        assert_formats(source, source);
+    }
+}
@@ -0,0 +1,170 @@
+//! Runs every `tests/**/*.jlx` script and checks what it does against the
+//! expectations written in its comments, in the style of the Crafting
+//! Interpreters test suite:
+//!
+//! - `// expect: value` is the next line `print` should write.
+//! - `// expect runtime error: message` is the error the script stops with,
+//!   raised on the line of the comment.
+//! - `// [line N] Error at 'x': message` is a line the scanner or parser
+//!   should report. A script with these is not run.
+
+use std::path::{Path, PathBuf};
+
+use crate::error::*;
+use crate::interpreter::*;
+use crate::parser::*;
+use crate::scanner::*;
+
+const EXPECT: &str = "// expect: ";
+const EXPECT_RUNTIME_ERROR: &str = "// expect runtime error: ";
+const EXPECT_ERROR: &str = "// [line ";
+
+/// What a script printed and how it ended, as lines comparable with the
+/// expectations.
+#[derive(Debug, Default, PartialEq)]
+struct Outcome {
+    output: Vec<String>,
+    errors: Vec<String>,
+    runtime_error: Option<String>,
+}
+
+impl Outcome {
+    fn expected(source: &str) -> Outcome {
+        let mut outcome = Outcome::default();
+        for (index, line) in source.lines().enumerate() {
+            if let Some(at) = line.find(EXPECT) {
+                outcome.output.push(line[at + EXPECT.len()..].to_string());
+            } else if let Some(at) = line.find(EXPECT_RUNTIME_ERROR) {
+                let message = &line[at + EXPECT_RUNTIME_ERROR.len()..];
+                outcome.runtime_error = Some(format!("[line {}] {message}", index + 1));
+            } else if let Some(at) = line.find(EXPECT_ERROR) {
+                // Keep the `[line N]`, drop the comment marker.
+                outcome.errors.push(line[at + 3..].to_string());
+            }
+        }
+        outcome
+    }
+
+    fn actual(source: &str) -> Outcome {
+        let (statements, errors) = capture_errors(|| {
+            let mut scanner = Scanner::new(source.to_string());
+            let tokens = scanner.scan_tokens().ok()?;
+            Parser::new(tokens).parse().ok()
+        });
+        let Some(statements) = statements else {
+            return Outcome {
+                errors,
+                ..Outcome::default()
+            };
+        };
+
+        let buffer = SharedBuffer::default();
+        let mut interpreter = Interpreter::with_output(Box::new(buffer.clone()));
+        let result = quietly(|| interpreter.interpret(&statements));
+        Outcome {
+            output: buffer.contents().lines().map(str::to_string).collect(),
+            errors,
+            runtime_error: result
+                .err()
+                .map(|e| format!("[line {}] {}", e.line(), e.message())),
+        }
+    }
+
+    fn lines(&self) -> Vec<String> {
+        let mut lines = self.output.clone();
+        lines.extend(self.errors.iter().cloned());
+        if let Some(error) = &self.runtime_error {
+            lines.push(format!("runtime error: {error}"));
+        }
+        lines
+    }
+}
+
+/// A line diff of `expected` against `actual`: unchanged lines start with
+/// two spaces, missing ones with `- ` and unexpected ones with `+ `.
+fn diff(expected: &[String], actual: &[String]) -> String {
+    // common[i][j] is the length of the longest common subsequence of
+    // expected[i..] and actual[j..].
+    let mut common = vec![vec![0; actual.len() + 1]; expected.len() + 1];
+    for i in (0..expected.len()).rev() {
+        for j in (0..actual.len()).rev() {
+            common[i][j] = if expected[i] == actual[j] {
+                common[i + 1][j + 1] + 1
+            } else {
+                common[i + 1][j].max(common[i][j + 1])
+            };
+        }
+    }
+
+    let mut text = String::new();
+    let (mut i, mut j) = (0, 0);
+    while i < expected.len() || j < actual.len() {
+        if i < expected.len() && j < actual.len() && expected[i] == actual[j] {
+            text.push_str(&format!("  {}\n", expected[i]));
+            i += 1;
+            j += 1;
+        } else if j == actual.len() || (i < expected.len() && common[i + 1][j] >= common[i][j + 1])
+        {
+            text.push_str(&format!("- {}\n", expected[i]));
+            i += 1;
+        } else {
+            text.push_str(&format!("+ {}\n", actual[j]));
+            j += 1;
+        }
+    }
+    text
+}
+
+fn find_scripts(dir: &Path, scripts: &mut Vec<PathBuf>) {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            find_scripts(&path, scripts);
+        } else if path.extension().is_some_and(|ext| ext == "jlx") {
+            scripts.push(path);
+        }
+    }
+}
+
+#[test]
+fn test_golden_files() {
+    let root = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests");
+    let mut scripts = Vec::new();
+    find_scripts(&root, &mut scripts);
+    scripts.sort();
+    assert!(!scripts.is_empty(), "no scripts under {}", root.display());
+
+    let mut failures = Vec::new();
+    for path in &scripts {
+        let source = std::fs::read_to_string(path).unwrap();
+        let expected = Outcome::expected(&source);
+        let actual = Outcome::actual(&source);
+        if expected != actual {
+            let name = path.strip_prefix(&root).unwrap_or(path).display();
+            failures.push(format!(
+                "{name}:\n{}",
+                diff(&expected.lines(), &actual.lines())
+            ));
+        }
+    }
+    assert!(
+        failures.is_empty(),
+        "{} of {} scripts failed:\n\n{}",
+        failures.len(),
+        scripts.len(),
+        failures.join("\n")
+    );
+}
+
+#[test]
+fn test_diff() {
+    let lines = |text: &str| -> Vec<String> { text.split(' ').map(str::to_string).collect() };
+    assert_eq!(
+        diff(&lines("a b c d"), &lines("a c x d")),
+        "  a\n- b\n  c\n+ x\n  d\n"
+    );
+    assert_eq!(diff(&[], &lines("a")), "+ a\n");
+}
@@ -1,18 +1,259 @@
+use std::cell::RefCell;
+use std::io::{self, BufWriter, Write};
 use std::rc::Rc;
 
+use crate::environment::*;
 use crate::error::*;
 use crate::expr::*;
+use crate::parser::*;
+use crate::scanner::*;
 use crate::stmt::*;
 use crate::literal::*;
+use crate::token::*;
 use crate::token_type::*;
 
-pub struct Interpreter {}
+pub struct Interpreter {
+    environment: Environment,
+    /// The script path followed by its command-line arguments, as read by
+    /// the natives `argc()` and `arg(i)`.
+    args: Vec<String>,
+    /// Where `print` writes.
+    output: Box<dyn Write>,
+}
 
-impl ExprVisitor<Literal> for Interpreter {
-    fn visit_binary_expr(&self, expr: &BinaryExpr) -> Result<Literal, JialoxError> {
+impl ExprVisitorMut<Literal> for Interpreter {
+    fn visit_binary_expr(&mut self, expr: &BinaryExpr) -> Result<Literal, JialoxError> {
         let left = self.evaluate(expr.left.clone())?;
         let right = self.evaluate(expr.right.clone())?;
-        let op = expr.operator.ttype();
+        self.binary_operation(&expr.operator, expr.operator.ttype(), left, right)
+    }
+
+    fn visit_assign_expr(&mut self, expr: &AssignExpr) -> Result<Literal, JialoxError> {
+        let value = self.evaluate(expr.value.clone())?;
+        self.environment.assign(&expr.name, value.clone())?;
+        Ok(value)
+    }
+
+    fn visit_call_expr(&mut self, expr: &CallExpr) -> Result<Literal, JialoxError> {
+        // There are no function values yet, so only natives can be called,
+        // and only by name.
+        let name = match &*expr.callee {
+            Expr::Variable(v) => v.name.lexeme(),
+            _ => {
+                return Err(JialoxError::error(
+                    expr.paren.line(),
+                    "Can only call functions.",
+                ))
+            }
+        };
+        let mut arguments = Vec::new();
+        for argument in &expr.arguments {
+            arguments.push(self.evaluate(argument.clone())?);
+        }
+        self.call_native(&name, &expr.paren, arguments)
+    }
+
+    fn visit_compound_assign_expr(
+        &mut self,
+        expr: &CompoundAssignExpr,
+    ) -> Result<Literal, JialoxError> {
+        let current = self.environment.get(&expr.name)?;
+        let value = self.evaluate(expr.value.clone())?;
+        let op = match expr.operator.ttype() {
+            TokenType::PlusEqual => TokenType::Plus,
+            TokenType::MinusEqual => TokenType::Minus,
+            TokenType::StarEqual => TokenType::Star,
+            TokenType::SlashEqual => TokenType::Slash,
+            TokenType::PercentEqual => TokenType::Percent,
+            _ => {
+                return Err(JialoxError::error(
+                    0,
+                    "Unreachable in struct Interpreter's method visit_compound_assign_expr()",
+                ))
+            }
+        };
+        let result = self.binary_operation(&expr.operator, op, current, value)?;
+        self.environment.assign(&expr.name, result.clone())?;
+        Ok(result)
+    }
+
+    fn visit_conditional_expr(&mut self, expr: &ConditionalExpr) -> Result<Literal, JialoxError> {
+        let condition = self.evaluate(expr.condition.clone())?;
+        if self.is_truthy(&condition) {
+            self.evaluate(expr.then_branch.clone())
+        } else {
+            self.evaluate(expr.else_branch.clone())
+        }
+    }
+
+    fn visit_grouping_expr(&mut self, expr: &GroupingExpr) -> Result<Literal, JialoxError> {
+        self.evaluate(expr.expression.clone())
+    }
+
+    fn visit_literal_expr(&mut self, expr: &LiteralExpr) -> Result<Literal, JialoxError> {
+        // Only a tree loaded from JSON can leave the value out.
+        expr.value
+            .clone()
+            .ok_or_else(|| JialoxError::error(expr.token.line(), "Literal has no value."))
+    }
+
+    fn visit_unary_expr(&mut self, expr: &UnaryExpr) -> Result<Literal, JialoxError> {
+        let right = self.evaluate(expr.right.clone())?;
+
+        match expr.operator.ttype() {
+            TokenType::Minus => match right {
+                Literal::Num(val) => Ok(Literal::Num(-val)),
+                _ => Ok(Literal::Nil),
+            },
+            TokenType::Bang => Ok(Literal::Bool(!self.is_truthy(&right))),
+            TokenType::Tilde => match Interpreter::as_integer(&right) {
+                Some(val) => Ok(Literal::Num(!val as f64)),
+                None => Err(JialoxError::error(
+                    expr.operator.line(),
+                    "Operand of '~' must be an integer.",
+                )),
+            },
+            _ => Err(JialoxError::error(
+                0,
+                "Unreachable in struct Interpreter's method visit_unary_expr()",
+            )),
+        }
+    }
+
+    fn visit_update_expr(&mut self, expr: &UpdateExpr) -> Result<Literal, JialoxError> {
+        let old = match self.environment.get(&expr.name)? {
+            Literal::Num(n) => n,
+            _ => {
+                return Err(JialoxError::error(
+                    expr.operator.line(),
+                    &format!("Operand of '{}' must be a number.", expr.operator.lexeme()),
+                ))
+            }
+        };
+        let new = if expr.operator.is(TokenType::PlusPlus) {
+            old + 1.0
+        } else {
+            old - 1.0
+        };
+        self.environment.assign(&expr.name, Literal::Num(new))?;
+        Ok(Literal::Num(if expr.prefix { new } else { old }))
+    }
+
+    fn visit_variable_expr(&mut self, expr: &VariableExpr) -> Result<Literal, JialoxError> {
+        self.environment.get(&expr.name)
+    }
+}
+
+impl StmtVisitorMut<()> for Interpreter {
+    fn visit_assert_stmt(&mut self, stmt: &AssertStmt) -> Result<(), JialoxError> {
+        let condition = self.evaluate(stmt.condition.clone())?;
+        if self.is_truthy(&condition) {
+            Ok(())
+        } else {
+            Err(JialoxError::error(stmt.keyword.line(), "Assertion failed."))
+        }
+    }
+
+    fn visit_expression_stmt(&mut self, stmt: &ExpressionStmt) -> Result<(), JialoxError> {
+        self.evaluate(stmt.expression.clone())?;
+        Ok(())
+    }
+
+    fn visit_print_stmt(&mut self, stmt: &PrintStmt) -> Result<(), JialoxError> {
+        let value = self.evaluate(stmt.expression.clone())?;
+        writeln!(self.output, "{value}").map_err(Interpreter::output_error)
+    }
+
+    /// Test blocks are skipped; `run_tests` runs them one at a time.
+    fn visit_test_stmt(&mut self, _stmt: &TestStmt) -> Result<(), JialoxError> {
+        Ok(())
+    }
+
+    fn visit_var_stmt(&mut self, stmt: &VarStmt) -> Result<(), JialoxError> {
+        let value = match &stmt.initializer {
+            Some(initializer) => self.evaluate(initializer.clone())?,
+            None => Literal::Nil,
+        };
+        self.environment.define(&stmt.name.lexeme(), value);
+        Ok(())
+    }
+}
+
+impl Interpreter {
+    pub fn new() -> Interpreter {
+        Interpreter::with_output(Box::new(io::stdout()))
+    }
+
+    /// An interpreter whose `print` statements write to `output`.
+    pub fn with_output(output: Box<dyn Write>) -> Interpreter {
+        Interpreter {
+            environment: Environment::new(),
+            args: Vec::new(),
+            output,
+        }
+    }
+
+    /// An interpreter that collects its output and writes it to stdout
+    /// when each call to `interpret` finishes, instead of line by line.
+    pub fn buffered() -> Interpreter {
+        Interpreter::with_output(Box::new(BufWriter::new(io::stdout())))
+    }
+
+    /// A new interpreter printing to `output` that starts with this one's
+    /// variables and arguments. Neither sees what the other does next.
+    pub fn fork(&self, output: Box<dyn Write>) -> Interpreter {
+        Interpreter {
+            environment: self.environment.clone(),
+            args: self.args.clone(),
+            output,
+        }
+    }
+
+    /// Makes the script path and its arguments visible to `argc()` and
+    /// `arg(i)`.
+    pub fn set_args(&mut self, args: Vec<String>) {
+        self.args = args;
+    }
+
+    /// Forgets every variable. The output sink and the arguments stay.
+    pub fn reset(&mut self) {
+        self.environment = Environment::new();
+    }
+
+    /// The global variables and their values, sorted by name.
+    pub fn globals(&self) -> Vec<(String, Literal)> {
+        self.environment.bindings()
+    }
+
+    fn evaluate(&mut self, expr: Rc<Expr>) -> Result<Literal, JialoxError> {
+        expr.accept_mut(self)
+    }
+
+    fn execute(&mut self, stmt: Rc<Stmt>) -> Result<(), JialoxError> {
+        stmt.accept_mut(self)
+    }
+
+    /// false and nil are falsey, and everything else is truthy.
+    fn is_truthy(&self, literal: &Literal) -> bool {
+        !matches!(literal, Literal::Bool(false) | Literal::Nil)
+    }
+
+    /// Applies `op` to two evaluated operands. `op` is passed separately from
+    /// `operator` so compound assignments can reuse it, e.g. `+=` as `+`.
+    fn binary_operation(
+        &self,
+        operator: &Token,
+        op: TokenType,
+        left: Literal,
+        right: Literal,
+    ) -> Result<Literal, JialoxError> {
+        if op == TokenType::Comma {
+            // Both operands were evaluated for their effects; the right one is the value.
+            return Ok(right);
+        }
+        if Interpreter::is_bitwise(op) {
+            return self.bitwise(operator, &left, &right);
+        }
 
         let result = match (left, right) {
             (Literal::Num(left), Literal::Num(right)) => match op {
@@ -20,6 +261,8 @@ impl ExprVisitor<Literal> for Interpreter {
                 TokenType::Minus => Literal::Num(left - right),
                 TokenType::Star => Literal::Num(left * right),
                 TokenType::Slash => Literal::Num(left / right),
+                TokenType::Percent => Literal::Num(left % right),
+                TokenType::StarStar => Literal::Num(left.powf(right)),
                 TokenType::Greater => Literal::Bool(left > right),
                 TokenType::GreaterEqual => Literal::Bool(left >= right),
                 TokenType::Less => Literal::Bool(left < right),
@@ -27,148 +270,284 @@ impl ExprVisitor<Literal> for Interpreter {
                 TokenType::BangEqual => Literal::Bool(left != right),
                 TokenType::EqualEqual => Literal::Bool(left == right),
                 _ => Literal::ArithmeticError,
-            }
+            },
             (Literal::Str(left), Literal::Str(right)) => match op {
                 TokenType::Plus => Literal::Str(format!("{left}{right}")),
                 TokenType::EqualEqual => Literal::Bool(left == right),
                 TokenType::BangEqual => Literal::Bool(left != right),
-                _ => Literal::ArithmeticError
-            }
+                _ => Literal::ArithmeticError,
+            },
             (Literal::Num(left), Literal::Str(right)) => match op {
                 TokenType::Plus => Literal::Str(format!("{left}{right}")),
                 TokenType::EqualEqual => Literal::Bool(false),
                 TokenType::BangEqual => Literal::Bool(true),
-                _ => Literal::ArithmeticError
-            }
+                _ => Literal::ArithmeticError,
+            },
             (Literal::Str(left), Literal::Num(right)) => match op {
                 TokenType::Plus => Literal::Str(format!("{left}{right}")),
                 TokenType::EqualEqual => Literal::Bool(false),
                 TokenType::BangEqual => Literal::Bool(true),
-                _ => Literal::ArithmeticError
-            }
+                _ => Literal::ArithmeticError,
+            },
             (Literal::Bool(left), Literal::Bool(right)) => match op {
                 TokenType::EqualEqual => Literal::Bool(left == right),
                 TokenType::BangEqual => Literal::Bool(left != right),
-                _ => Literal::ArithmeticError
-            }
+                _ => Literal::ArithmeticError,
+            },
             (Literal::Nil, Literal::Nil) => match op {
                 TokenType::EqualEqual => Literal::Bool(true),
                 TokenType::BangEqual => Literal::Bool(false),
-                _ => Literal::ArithmeticError
-            }
+                _ => Literal::ArithmeticError,
+            },
             (Literal::Nil, _) => match op {
                 TokenType::EqualEqual => Literal::Bool(false),
                 TokenType::BangEqual => Literal::Bool(true),
-                _ => Literal::ArithmeticError
-            }
+                _ => Literal::ArithmeticError,
+            },
             _ => Literal::ArithmeticError,
         };
         if result == Literal::ArithmeticError {
-            Err(JialoxError::error(
-                expr.operator.line(),
-                "Illegal expression",
-            ))
+            Err(JialoxError::error(operator.line(), "Illegal expression"))
         } else {
             Ok(result)
         }
     }
 
-    fn visit_grouping_expr(&self, expr: &GroupingExpr) -> Result<Literal, JialoxError> {
-        self.evaluate(expr.expression.clone())
+    fn is_bitwise(ttype: TokenType) -> bool {
+        matches!(
+            ttype,
+            TokenType::Ampersand
+                | TokenType::Pipe
+                | TokenType::Caret
+                | TokenType::LessLess
+                | TokenType::GreaterGreater
+        )
     }
 
-    fn visit_literal_expr(&self, expr: &LiteralExpr) -> Result<Literal, JialoxError> {
-        Ok(expr.value.clone().unwrap())
+    /// Bitwise operators work on numbers with no fractional part that fit
+    /// in an i64.
+    fn as_integer(literal: &Literal) -> Option<i64> {
+        match literal {
+            Literal::Num(n)
+                if n.fract() == 0.0 && *n >= i64::MIN as f64 && *n < i64::MAX as f64 =>
+            {
+                Some(*n as i64)
+            }
+            _ => None,
+        }
     }
 
-    fn visit_unary_expr(&self, expr: &UnaryExpr) -> Result<Literal, JialoxError> {
-        let right = self.evaluate(expr.right.clone())?;
+    fn call_native(
+        &mut self,
+        name: &str,
+        paren: &Token,
+        arguments: Vec<Literal>,
+    ) -> Result<Literal, JialoxError> {
+        let arity = match name {
+            "argc" => 0,
+            "arg" | "exit" => 1,
+            "assert_eq" => 3,
+            _ => {
+                return Err(JialoxError::error(
+                    paren.line(),
+                    &format!("Undefined function '{name}'."),
+                ))
+            }
+        };
+        if arguments.len() != arity {
+            return Err(JialoxError::error(
+                paren.line(),
+                &format!("Expected {arity} arguments but got {}.", arguments.len()),
+            ));
+        }
 
-        match expr.operator.ttype() {
-            TokenType::Minus => match right {
-                Literal::Num(val) => Ok(Literal::Num(-val)),
-                _ => Ok(Literal::Nil),
+        match name {
+            "argc" => Ok(Literal::Num(self.args.len() as f64)),
+            "assert_eq" => {
+                let [left, right, message] = &arguments[..] else {
+                    unreachable!("arity was checked above");
+                };
+                if left == right {
+                    Ok(Literal::Nil)
+                } else {
+                    Err(JialoxError::error(
+                        paren.line(),
+                        &format!("{message}: {} != {}", show(left), show(right)),
+                    ))
+                }
+            }
+            "exit" => match Interpreter::as_integer(&arguments[0]) {
+                Some(code) if i32::try_from(code).is_ok() => {
+                    Err(JialoxError::exit(paren.line(), code as i32))
+                }
+                _ => Err(JialoxError::error(
+                    paren.line(),
+                    "Exit code must be an integer.",
+                )),
+            },
+            _ => match Interpreter::as_integer(&arguments[0]) {
+                Some(i) if i >= 0 && (i as usize) < self.args.len() => {
+                    Ok(Literal::Str(self.args[i as usize].clone()))
+                }
+                Some(_) => Ok(Literal::Nil),
+                None => Err(JialoxError::error(
+                    paren.line(),
+                    "Argument index must be an integer.",
+                )),
             },
-            TokenType::Bang => Ok(Literal::Bool(!self.is_truthy(&right))),
-            _ => Err(JialoxError::error(
-                0,
-                "Unreachable in struct Interpreter's method visit_unary_expr()",
-            )),
         }
     }
-}
 
-impl StmtVisitor<()> for Interpreter {
-    fn visit_expression_stmt(&self, stmt: &ExpressionStmt) -> Result<(), JialoxError> {
-        self.evaluate(stmt.expression.clone())?;
-        Ok(())
+    fn bitwise(
+        &self,
+        operator: &Token,
+        left: &Literal,
+        right: &Literal,
+    ) -> Result<Literal, JialoxError> {
+        let (left, right) = match (
+            Interpreter::as_integer(left),
+            Interpreter::as_integer(right),
+        ) {
+            (Some(left), Some(right)) => (left, right),
+            _ => {
+                return Err(JialoxError::error(
+                    operator.line(),
+                    &format!("Operands of '{}' must be integers.", operator.lexeme()),
+                ))
+            }
+        };
+
+        let result = match operator.ttype() {
+            TokenType::Ampersand => left & right,
+            TokenType::Pipe => left | right,
+            TokenType::Caret => left ^ right,
+            TokenType::LessLess | TokenType::GreaterGreater => {
+                if !(0..64).contains(&right) {
+                    return Err(JialoxError::error(
+                        operator.line(),
+                        "Shift amount must be between 0 and 63.",
+                    ));
+                }
+                if operator.is(TokenType::LessLess) {
+                    left << right
+                } else {
+                    left >> right
+                }
+            }
+            _ => {
+                return Err(JialoxError::error(
+                    0,
+                    "Unreachable in struct Interpreter's method bitwise()",
+                ))
+            }
+        };
+        Ok(Literal::Num(result as f64))
     }
 
-    fn visit_print_stmt(&self, stmt: &PrintStmt) -> Result<(), JialoxError> {
-        let value = self.evaluate(stmt.expression.clone())?;
-        println!("{value}");
-        Ok(())
+    pub fn interpret(&mut self, statements: &[Rc<Stmt>]) -> Result<(), JialoxError> {
+        let result = statements
+            .iter()
+            .try_for_each(|statement| self.execute(statement.clone()));
+        let flushed = self.output.flush();
+        result?;
+        flushed.map_err(Interpreter::output_error)
     }
-}
 
-impl Interpreter {
-    pub fn new() -> Interpreter {
-        Interpreter { }
+    /// Like `interpret`, but hands back the value of the last statement if
+    /// it is an expression statement, for the REPL to show.
+    pub fn interpret_repl(
+        &mut self,
+        statements: &[Rc<Stmt>],
+    ) -> Result<Option<Literal>, JialoxError> {
+        let Some((last, rest)) = statements.split_last() else {
+            return Ok(None);
+        };
+        self.interpret(rest)?;
+        let result = match &**last {
+            Stmt::Expression(stmt) => self.evaluate(stmt.expression.clone()).map(Some),
+            _ => self.execute(last.clone()).map(|()| None),
+        };
+        let flushed = self.output.flush();
+        let value = result?;
+        flushed.map_err(Interpreter::output_error)?;
+        Ok(value)
     }
 
-    fn evaluate(&self, expr: Rc<Expr>) -> Result<Literal, JialoxError> {
-        expr.accept(self)
+    fn output_error(e: io::Error) -> JialoxError {
+        JialoxError::error(0, &format!("Could not write output: {e}"))
     }
+}
+
+/// An output sink that keeps everything written to it. Clones share the
+/// same buffer, so one can be handed to an `Interpreter` and the other
+/// read afterwards.
+#[derive(Clone, Default)]
+pub struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
 
-    fn execute(&self, stmt: Rc<Stmt>) -> Result<(), JialoxError> {
-        stmt.accept(self)
+impl SharedBuffer {
+    pub fn contents(&self) -> String {
+        String::from_utf8_lossy(&self.0.borrow()).into_owned()
     }
+}
 
-    /// false and nil are falsey, and everything else is truthy.
-    fn is_truthy(&self, literal: &Literal) -> bool {
-        !matches!(literal, Literal::Bool(false) | Literal::Nil)
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
     }
 
-    pub fn interpret(&self, statements: &[Rc<Stmt>]) -> Result<(), JialoxError> {
-        for statement in statements {
-            self.execute(statement.clone())?;
-        }
+    fn flush(&mut self) -> io::Result<()> {
         Ok(())
     }
 }
 
+/// Runs `source` as a script and returns everything it printed.
+#[allow(dead_code)] // for embedders; only tests call it so far
+pub fn capture_output(source: &str) -> Result<String, JialoxError> {
+    let mut scanner = Scanner::new(source.to_string());
+    let tokens = scanner.scan_tokens()?;
+    let statements = Parser::new(tokens).parse()?;
+    let buffer = SharedBuffer::default();
+    Interpreter::with_output(Box::new(buffer.clone())).interpret(&statements)?;
+    Ok(buffer.contents())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::token::*;
 
-    fn new_literal_number(n: f64) -> Rc<Expr> {
+    fn new_literal(ttype: TokenType, lexeme: &str, value: Literal) -> Rc<Expr> {
+        let token = Token::new(ttype, lexeme.to_string(), Some(value.clone()), 1);
         Rc::new(Expr::Literal(Rc::new(LiteralExpr {
-            value: Some(Literal::Num(n)),
+            token,
+            value: Some(value),
         })))
     }
 
+    fn new_literal_number(n: f64) -> Rc<Expr> {
+        new_literal(TokenType::Number, &n.to_string(), Literal::Num(n))
+    }
+
     fn new_literal_boolean(b: bool) -> Rc<Expr> {
-        Rc::new(Expr::Literal(Rc::new(LiteralExpr {
-            value: Some(Literal::Bool(b)),
-        })))
+        let ttype = if b { TokenType::True } else { TokenType::False };
+        new_literal(ttype, &b.to_string(), Literal::Bool(b))
     }
 
     fn new_literal_str(s: &str) -> Rc<Expr> {
-        Rc::new(Expr::Literal(Rc::new(LiteralExpr {
-            value: Some(Literal::Str(s.to_string())),
-        })))
+        new_literal(
+            TokenType::String,
+            &format!("\"{s}\""),
+            Literal::Str(s.to_string()),
+        )
     }
 
     fn new_literal_nir() -> Rc<Expr> {
-        Rc::new(Expr::Literal(Rc::new(LiteralExpr {
-            value: Some(Literal::Nil),
-        })))
+        new_literal(TokenType::Nil, "nil", Literal::Nil)
     }
 
     fn run_comparison_tests(tok: &Token, cmps_result: Vec<bool>) {
-        let nums = vec![7.7, 7.8, 7.9];
-        let terp = Interpreter {};
+        let nums = [7.7, 7.8, 7.9];
+        let mut terp = Interpreter::new();
 
         for (&num, ret) in nums.iter().zip(cmps_result) {
             let binary_expr = BinaryExpr {
@@ -184,7 +563,7 @@ mod tests {
 
     #[test]
     fn test_unary_minus() {
-        let terp = Interpreter {};
+        let mut terp = Interpreter::new();
         let unary_expr = UnaryExpr {
             operator: Token::new(TokenType::Minus, "-".to_string(), None, 123),
             right: new_literal_number(57.8),
@@ -196,7 +575,7 @@ mod tests {
 
     #[test]
     fn test_unary_bang() {
-        let terp = Interpreter {};
+        let mut terp = Interpreter::new();
         let unary_expr = UnaryExpr {
             operator: Token::new(TokenType::Bang, "!".to_string(), None, 123),
             right: new_literal_boolean(false),
@@ -208,7 +587,7 @@ mod tests {
 
     #[test]
     fn test_addition() {
-        let terp = Interpreter {};
+        let mut terp = Interpreter::new();
         let binary_expr = BinaryExpr {
             left: new_literal_number(7.8),
             operator: Token::new(TokenType::Plus, "+".to_string(), None, 123),
@@ -221,7 +600,7 @@ mod tests {
 
     #[test]
     fn test_string_concatination() {
-        let terp = Interpreter {};
+        let mut terp = Interpreter::new();
         let binary_expr = BinaryExpr {
             left: new_literal_str("hello"),
             operator: Token::new(TokenType::Plus, "+".to_string(), None, 123),
@@ -237,7 +616,7 @@ mod tests {
 
     #[test]
     fn test_substraction() {
-        let terp = Interpreter {};
+        let mut terp = Interpreter::new();
         let binary_expr = BinaryExpr {
             left: new_literal_number(7.8),
             operator: Token::new(TokenType::Minus, "-".to_string(), None, 123),
@@ -250,7 +629,7 @@ mod tests {
 
     #[test]
     fn test_multiplication() {
-        let terp = Interpreter {};
+        let mut terp = Interpreter::new();
         let binary_expr = BinaryExpr {
             left: new_literal_number(7.8),
             operator: Token::new(TokenType::Star, "*".to_string(), None, 123),
@@ -263,7 +642,7 @@ mod tests {
 
     #[test]
     fn test_division() {
-        let terp = Interpreter {};
+        let mut terp = Interpreter::new();
         let binary_expr = BinaryExpr {
             left: new_literal_number(7.8),
             operator: Token::new(TokenType::Slash, "/".to_string(), None, 123),
@@ -276,7 +655,7 @@ mod tests {
 
     #[test]
     fn test_arithmetic_error_for_substraction() {
-        let terp = Interpreter {};
+        let mut terp = Interpreter::new();
         let binary_expr = BinaryExpr {
             left: new_literal_number(7.8),
             operator: Token::new(TokenType::Minus, "-".to_string(), None, 123),
@@ -331,7 +710,7 @@ mod tests {
 
     #[test]
     fn test_equaleuqal_string() {
-        let terp = Interpreter {};
+        let mut terp = Interpreter::new();
         let binary_expr = BinaryExpr {
             left: new_literal_str("hello"),
             operator: Token::new(TokenType::EqualEqual, "==".to_string(), None, 123),
@@ -344,7 +723,7 @@ mod tests {
 
     #[test]
     fn test_equaleuqal_bool() {
-        let terp = Interpreter {};
+        let mut terp = Interpreter::new();
         let binary_expr = BinaryExpr {
             left: new_literal_boolean(true),
             operator: Token::new(TokenType::EqualEqual, "==".to_string(), None, 123),
@@ -357,7 +736,7 @@ mod tests {
 
     #[test]
     fn test_equaleuqal_nil() {
-        let terp = Interpreter {};
+        let mut terp = Interpreter::new();
         let binary_expr = BinaryExpr {
             left: new_literal_nir(),
             operator: Token::new(TokenType::EqualEqual, "==".to_string(), None, 123),
@@ -378,7 +757,7 @@ mod tests {
 
     #[test]
     fn test_bangeuqal_string() {
-        let terp = Interpreter {};
+        let mut terp = Interpreter::new();
         let binary_expr = BinaryExpr {
             left: new_literal_str("hello"),
             operator: Token::new(TokenType::BangEqual, "!=".to_string(), None, 123),
@@ -391,7 +770,7 @@ mod tests {
 
     #[test]
     fn test_bangeuqal_bool() {
-        let terp = Interpreter {};
+        let mut terp = Interpreter::new();
         let binary_expr = BinaryExpr {
             left: new_literal_boolean(true),
             operator: Token::new(TokenType::BangEqual, "!=".to_string(), None, 123),
@@ -404,7 +783,7 @@ mod tests {
 
     #[test]
     fn test_bangeuqal_nil() {
-        let terp = Interpreter {};
+        let mut terp = Interpreter::new();
         let binary_expr = BinaryExpr {
             left: new_literal_nir(),
             operator: Token::new(TokenType::BangEqual, "!=".to_string(), None, 123),
@@ -417,7 +796,7 @@ mod tests {
 
     #[test]
     fn test_bangeuqal_random() {
-        let terp = Interpreter {};
+        let mut terp = Interpreter::new();
         let binary_expr = BinaryExpr {
             left: new_literal_nir(),
             operator: Token::new(TokenType::BangEqual, "!=".to_string(), None, 123),
@@ -427,4 +806,313 @@ mod tests {
         assert!(result.is_ok());
         assert_eq!(result.ok(), Some(Literal::Bool(true)));
     }
+
+    fn run_binary_numbers(left: f64, ttype: TokenType, right: f64) -> Option<Literal> {
+        let mut terp = Interpreter::new();
+        let binary_expr = BinaryExpr {
+            left: new_literal_number(left),
+            operator: Token::new(ttype, format!("{ttype:?}"), None, 123),
+            right: new_literal_number(right),
+        };
+        terp.visit_binary_expr(&binary_expr).ok()
+    }
+
+    #[test]
+    fn test_modulo() {
+        let result = run_binary_numbers(7.0, TokenType::Percent, 3.0);
+        assert_eq!(result, Some(Literal::Num(1.0)));
+        let result = run_binary_numbers(-7.5, TokenType::Percent, 2.0);
+        assert_eq!(result, Some(Literal::Num(-1.5)));
+    }
+
+    #[test]
+    fn test_power() {
+        let result = run_binary_numbers(2.0, TokenType::StarStar, 10.0);
+        assert_eq!(result, Some(Literal::Num(1024.0)));
+        let result = run_binary_numbers(4.0, TokenType::StarStar, 0.5);
+        assert_eq!(result, Some(Literal::Num(2.0)));
+    }
+
+    #[test]
+    fn test_bitwise_and_or_xor() {
+        let result = run_binary_numbers(12.0, TokenType::Ampersand, 10.0);
+        assert_eq!(result, Some(Literal::Num(8.0)));
+        let result = run_binary_numbers(12.0, TokenType::Pipe, 10.0);
+        assert_eq!(result, Some(Literal::Num(14.0)));
+        let result = run_binary_numbers(12.0, TokenType::Caret, 10.0);
+        assert_eq!(result, Some(Literal::Num(6.0)));
+    }
+
+    #[test]
+    fn test_shifts() {
+        let result = run_binary_numbers(3.0, TokenType::LessLess, 4.0);
+        assert_eq!(result, Some(Literal::Num(48.0)));
+        let result = run_binary_numbers(-16.0, TokenType::GreaterGreater, 2.0);
+        assert_eq!(result, Some(Literal::Num(-4.0)));
+        let result = run_binary_numbers(1.0, TokenType::LessLess, 64.0);
+        assert_eq!(result, None);
+    }
+
+    #[test]
+    fn test_bitwise_requires_integers() {
+        let result = run_binary_numbers(1.5, TokenType::Ampersand, 1.0);
+        assert_eq!(result, None);
+
+        let mut terp = Interpreter::new();
+        let binary_expr = BinaryExpr {
+            left: new_literal_str("1"),
+            operator: Token::new(TokenType::Pipe, "|".to_string(), None, 123),
+            right: new_literal_number(1.0),
+        };
+        assert!(terp.visit_binary_expr(&binary_expr).is_err());
+    }
+
+    #[test]
+    fn test_unary_tilde() {
+        let mut terp = Interpreter::new();
+        let unary_expr = UnaryExpr {
+            operator: Token::new(TokenType::Tilde, "~".to_string(), None, 123),
+            right: new_literal_number(5.0),
+        };
+        let result = terp.visit_unary_expr(&unary_expr);
+        assert_eq!(result.ok(), Some(Literal::Num(-6.0)));
+
+        let unary_expr = UnaryExpr {
+            operator: Token::new(TokenType::Tilde, "~".to_string(), None, 123),
+            right: new_literal_number(0.25),
+        };
+        assert!(terp.visit_unary_expr(&unary_expr).is_err());
+    }
+
+    fn new_binary(left: Rc<Expr>, ttype: TokenType, lexeme: &str, right: Rc<Expr>) -> Rc<Expr> {
+        Rc::new(Expr::Binary(Rc::new(BinaryExpr {
+            left,
+            operator: Token::new(ttype, lexeme.to_string(), None, 123),
+            right,
+        })))
+    }
+
+    #[test]
+    fn test_conditional_takes_then_branch() {
+        let mut terp = Interpreter::new();
+        let conditional_expr = ConditionalExpr {
+            condition: new_literal_boolean(true),
+            then_branch: new_literal_number(1.0),
+            else_branch: new_binary(
+                new_literal_str("a"),
+                TokenType::Minus,
+                "-",
+                new_literal_nir(),
+            ),
+        };
+        let result = terp.visit_conditional_expr(&conditional_expr);
+        assert_eq!(result.ok(), Some(Literal::Num(1.0)));
+    }
+
+    #[test]
+    fn test_conditional_takes_else_branch() {
+        let mut terp = Interpreter::new();
+        let conditional_expr = ConditionalExpr {
+            condition: new_literal_nir(),
+            then_branch: new_binary(
+                new_literal_str("a"),
+                TokenType::Minus,
+                "-",
+                new_literal_nir(),
+            ),
+            else_branch: new_literal_number(2.0),
+        };
+        let result = terp.visit_conditional_expr(&conditional_expr);
+        assert_eq!(result.ok(), Some(Literal::Num(2.0)));
+    }
+
+    #[test]
+    fn test_comma_yields_right_operand() {
+        let mut terp = Interpreter::new();
+        let binary_expr = BinaryExpr {
+            left: new_literal_str("ignored"),
+            operator: Token::new(TokenType::Comma, ",".to_string(), None, 123),
+            right: new_literal_boolean(false),
+        };
+        let result = terp.visit_binary_expr(&binary_expr);
+        assert_eq!(result.ok(), Some(Literal::Bool(false)));
+    }
+
+    #[test]
+    fn test_comma_propagates_left_error() {
+        let mut terp = Interpreter::new();
+        let binary_expr = BinaryExpr {
+            left: new_binary(
+                new_literal_str("a"),
+                TokenType::Minus,
+                "-",
+                new_literal_nir(),
+            ),
+            operator: Token::new(TokenType::Comma, ",".to_string(), None, 123),
+            right: new_literal_boolean(false),
+        };
+        assert!(terp.visit_binary_expr(&binary_expr).is_err());
+    }
+
+    fn define_number(terp: &mut Interpreter, name: &str, n: f64) -> Token {
+        let name = Token::new(TokenType::Identifier, name.to_string(), None, 123);
+        let var_stmt = VarStmt {
+            name: name.mirror(),
+            initializer: Some(new_literal_number(n)),
+            doc: None,
+        };
+        assert!(terp.visit_var_stmt(&var_stmt).is_ok());
+        name
+    }
+
+    fn read_variable(terp: &mut Interpreter, name: &Token) -> Option<Literal> {
+        let variable_expr = VariableExpr {
+            name: name.mirror(),
+        };
+        terp.visit_variable_expr(&variable_expr).ok()
+    }
+
+    #[test]
+    fn test_assign_undefined_variable() {
+        let mut terp = Interpreter::new();
+        let assign_expr = AssignExpr {
+            name: Token::new(TokenType::Identifier, "x".to_string(), None, 123),
+            value: new_literal_number(1.0),
+        };
+        assert!(terp.visit_assign_expr(&assign_expr).is_err());
+    }
+
+    #[test]
+    fn test_compound_assign() {
+        let mut terp = Interpreter::new();
+        let name = define_number(&mut terp, "x", 7.0);
+        let compound_expr = CompoundAssignExpr {
+            name: name.mirror(),
+            operator: Token::new(TokenType::StarEqual, "*=".to_string(), None, 123),
+            value: new_literal_number(3.0),
+        };
+        let result = terp.visit_compound_assign_expr(&compound_expr);
+        assert_eq!(result.ok(), Some(Literal::Num(21.0)));
+        assert_eq!(read_variable(&mut terp, &name), Some(Literal::Num(21.0)));
+
+        let compound_expr = CompoundAssignExpr {
+            name: name.mirror(),
+            operator: Token::new(TokenType::PercentEqual, "%=".to_string(), None, 123),
+            value: new_literal_number(4.0),
+        };
+        let result = terp.visit_compound_assign_expr(&compound_expr);
+        assert_eq!(result.ok(), Some(Literal::Num(1.0)));
+    }
+
+    #[test]
+    fn test_compound_assign_type_error_keeps_value() {
+        let mut terp = Interpreter::new();
+        let name = define_number(&mut terp, "x", 7.0);
+        let compound_expr = CompoundAssignExpr {
+            name: name.mirror(),
+            operator: Token::new(TokenType::MinusEqual, "-=".to_string(), None, 123),
+            value: new_literal_str("a"),
+        };
+        assert!(terp.visit_compound_assign_expr(&compound_expr).is_err());
+        assert_eq!(read_variable(&mut terp, &name), Some(Literal::Num(7.0)));
+    }
+
+    #[test]
+    fn test_prefix_and_postfix_update() {
+        let mut terp = Interpreter::new();
+        let name = define_number(&mut terp, "i", 1.0);
+        let update_expr = UpdateExpr {
+            name: name.mirror(),
+            operator: Token::new(TokenType::PlusPlus, "++".to_string(), None, 123),
+            prefix: false,
+        };
+        let result = terp.visit_update_expr(&update_expr);
+        assert_eq!(result.ok(), Some(Literal::Num(1.0)));
+        assert_eq!(read_variable(&mut terp, &name), Some(Literal::Num(2.0)));
+
+        let update_expr = UpdateExpr {
+            name: name.mirror(),
+            operator: Token::new(TokenType::MinusMinus, "--".to_string(), None, 123),
+            prefix: true,
+        };
+        let result = terp.visit_update_expr(&update_expr);
+        assert_eq!(result.ok(), Some(Literal::Num(1.0)));
+        assert_eq!(read_variable(&mut terp, &name), Some(Literal::Num(1.0)));
+    }
+
+    #[test]
+    fn test_interpret_repl_returns_last_expression() {
+        let parse = |source: &str| {
+            let mut scanner = crate::scanner::Scanner::new(source.to_string());
+            let tokens = scanner.scan_tokens().unwrap();
+            crate::parser::Parser::repl(tokens).parse().unwrap()
+        };
+        let mut terp = Interpreter::new();
+        assert_eq!(terp.interpret_repl(&parse("var a = 2;")).unwrap(), None);
+        assert_eq!(
+            terp.interpret_repl(&parse("a += 1; a * 2")).unwrap(),
+            Some(Literal::Num(6.0))
+        );
+        assert_eq!(
+            terp.interpret_repl(&parse("a;")).unwrap(),
+            Some(Literal::Num(3.0))
+        );
+        assert_eq!(terp.interpret_repl(&[]).unwrap(), None);
+    }
+
+    #[test]
+    fn test_script_arguments() {
+        let parse = |source: &str| {
+            let mut scanner = crate::scanner::Scanner::new(source.to_string());
+            let tokens = scanner.scan_tokens().unwrap();
+            crate::parser::Parser::repl(tokens).parse().unwrap()
+        };
+        let mut terp = Interpreter::new();
+        terp.set_args(vec!["script.jlx".to_string(), "one".to_string()]);
+        let mut value = |source: &str| terp.interpret_repl(&parse(source)).unwrap();
+        assert_eq!(value("argc()"), Some(Literal::Num(2.0)));
+        assert_eq!(
+            value("arg(0)"),
+            Some(Literal::Str("script.jlx".to_string()))
+        );
+        assert_eq!(value("arg(1)"), Some(Literal::Str("one".to_string())));
+        assert_eq!(value("arg(2)"), Some(Literal::Nil));
+
+        assert!(terp.interpret_repl(&parse("arg()")).is_err());
+        assert!(terp.interpret_repl(&parse("arg(0.5)")).is_err());
+        assert!(terp.interpret_repl(&parse("nope()")).is_err());
+        assert!(terp.interpret_repl(&parse("(1)()")).is_err());
+    }
+
+    #[test]
+    fn test_exit_unwinds_with_its_code() {
+        let mut scanner = crate::scanner::Scanner::new("exit(3); undefined;".to_string());
+        let tokens = scanner.scan_tokens().unwrap();
+        let statements = crate::parser::Parser::new(tokens).parse().unwrap();
+        let err = Interpreter::new().interpret(&statements).unwrap_err();
+        assert_eq!(err.exit_code(), Some(3));
+
+        let mut scanner = crate::scanner::Scanner::new("exit(\"no\");".to_string());
+        let tokens = scanner.scan_tokens().unwrap();
+        let statements = crate::parser::Parser::new(tokens).parse().unwrap();
+        let err = Interpreter::new().interpret(&statements).unwrap_err();
+        assert_eq!(err.exit_code(), None);
+    }
+
+    #[test]
+    fn test_print_writes_to_the_output_sink() {
+        assert_eq!(
+            capture_output("var a = 1; print a; print \"two\"; print a == 1;").unwrap(),
+            "1\ntwo\ntrue\n"
+        );
+        assert!(quietly(|| capture_output("print 1 +;")).is_err());
+
+        // What was printed before a runtime error is kept.
+        let buffer = SharedBuffer::default();
+        let mut terp = Interpreter::with_output(Box::new(buffer.clone()));
+        let mut scanner = crate::scanner::Scanner::new("print 1; print -nope;".to_string());
+        let statements = Parser::new(scanner.scan_tokens().unwrap()).parse().unwrap();
+        assert!(quietly(|| terp.interpret(&statements)).is_err());
+        assert_eq!(buffer.contents(), "1\n");
+    }
 }
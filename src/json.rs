@@ -0,0 +1,654 @@
+use std::fmt;
+use std::rc::Rc;
+
+use crate::literal::*;
+use crate::stmt::*;
+use crate::token::*;
+use crate::token_type::*;
+
+/// A parsed JSON document. Object members keep their source order so that
+/// exported trees read `kind` first.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Json {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+#[derive(Debug)]
+pub struct JsonError {
+    message: String,
+}
+
+impl JsonError {
+    pub fn new(message: String) -> JsonError {
+        JsonError { message }
+    }
+}
+
+impl fmt::Display for JsonError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+/// Conversion of syntax trees and their parts to JSON. The node impls are
+/// generated from grammar/ast.def alongside the trees themselves.
+pub trait ToJson {
+    fn to_json(&self) -> Json;
+}
+
+pub trait FromJson: Sized {
+    fn from_json(json: &Json) -> Result<Self, JsonError>;
+}
+
+/// Exports a parsed program as a JSON array of statements. Every node is an
+/// object whose `kind` names it; tokens carry their `line` and the `start`
+/// and `end` character offsets of their lexeme.
+pub fn program_to_json(statements: &[Rc<Stmt>]) -> String {
+    statements.to_json().to_string()
+}
+
+/// Loads a program exported by `program_to_json`, ready to interpret.
+pub fn program_from_json(source: &str) -> Result<Vec<Rc<Stmt>>, JsonError> {
+    Vec::from_json(&Json::parse(source)?)
+}
+
+impl Json {
+    pub fn parse(source: &str) -> Result<Json, JsonError> {
+        let mut reader = Reader {
+            source: source.chars().collect(),
+            current: 0,
+        };
+        let value = reader.value()?;
+        reader.skip_whitespace();
+        if reader.current < reader.source.len() {
+            return Err(reader.error("Unexpected data after JSON value."));
+        }
+        Ok(value)
+    }
+
+    /// An object for a tree node: `kind` followed by the node's fields.
+    pub fn node(kind: &str, fields: Vec<(&str, Json)>) -> Json {
+        let mut members = vec![("kind".to_string(), Json::String(kind.to_string()))];
+        for (name, value) in fields {
+            members.push((name.to_string(), value));
+        }
+        Json::Object(members)
+    }
+
+    pub fn kind(&self) -> Result<&str, JsonError> {
+        match self.field("kind")? {
+            Json::String(kind) => Ok(kind),
+            _ => Err(JsonError::new("Node 'kind' must be a string.".to_string())),
+        }
+    }
+
+    pub fn field(&self, name: &str) -> Result<&Json, JsonError> {
+        match self {
+            Json::Object(members) => members
+                .iter()
+                .find(|(key, _)| key == name)
+                .map(|(_, value)| value)
+                .ok_or_else(|| JsonError::new(format!("Missing field '{name}'."))),
+            _ => Err(JsonError::new(format!(
+                "Expected an object with field '{name}'."
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for Json {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Json::Null => write!(f, "null"),
+            Json::Bool(b) => write!(f, "{b}"),
+            Json::Number(n) => write!(f, "{n}"),
+            Json::String(s) => write_string(f, s),
+            Json::Array(items) => {
+                write!(f, "[")?;
+                for (i, item) in items.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{item}")?;
+                }
+                write!(f, "]")
+            }
+            Json::Object(members) => {
+                write!(f, "{{")?;
+                for (i, (key, value)) in members.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write_string(f, key)?;
+                    write!(f, ":{value}")?;
+                }
+                write!(f, "}}")
+            }
+        }
+    }
+}
+
+fn write_string(f: &mut fmt::Formatter<'_>, s: &str) -> fmt::Result {
+    write!(f, "\"")?;
+    for ch in s.chars() {
+        match ch {
+            '"' => write!(f, "\\\"")?,
+            '\\' => write!(f, "\\\\")?,
+            '\n' => write!(f, "\\n")?,
+            '\r' => write!(f, "\\r")?,
+            '\t' => write!(f, "\\t")?,
+            _ if (ch as u32) < 0x20 => write!(f, "\\u{:04x}", ch as u32)?,
+            _ => write!(f, "{ch}")?,
+        }
+    }
+    write!(f, "\"")
+}
+
+struct Reader {
+    source: Vec<char>,
+    current: usize,
+}
+
+impl Reader {
+    fn error(&self, message: &str) -> JsonError {
+        JsonError::new(format!("{message} (at character {})", self.current))
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.source.get(self.current).copied()
+    }
+
+    fn advance(&mut self) -> Option<char> {
+        let ch = self.peek();
+        self.current += 1;
+        ch
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(' ' | '\t' | '\n' | '\r') = self.peek() {
+            self.current += 1;
+        }
+    }
+
+    fn consume(&mut self, expected: char) -> Result<(), JsonError> {
+        self.skip_whitespace();
+        if self.peek() == Some(expected) {
+            self.current += 1;
+            Ok(())
+        } else {
+            Err(self.error(&format!("Expected '{expected}'.")))
+        }
+    }
+
+    fn keyword(&mut self, word: &str, value: Json) -> Result<Json, JsonError> {
+        for expected in word.chars() {
+            if self.advance() != Some(expected) {
+                return Err(self.error(&format!("Expected '{word}'.")));
+            }
+        }
+        Ok(value)
+    }
+
+    fn value(&mut self) -> Result<Json, JsonError> {
+        self.skip_whitespace();
+        match self.peek() {
+            Some('n') => self.keyword("null", Json::Null),
+            Some('t') => self.keyword("true", Json::Bool(true)),
+            Some('f') => self.keyword("false", Json::Bool(false)),
+            Some('"') => Ok(Json::String(self.string()?)),
+            Some('[') => self.array(),
+            Some('{') => self.object(),
+            Some('-' | '0'..='9') => self.number(),
+            _ => Err(self.error("Expected a JSON value.")),
+        }
+    }
+
+    fn array(&mut self) -> Result<Json, JsonError> {
+        self.consume('[')?;
+        let mut items = Vec::new();
+        self.skip_whitespace();
+        if self.peek() == Some(']') {
+            self.current += 1;
+            return Ok(Json::Array(items));
+        }
+        loop {
+            items.push(self.value()?);
+            self.skip_whitespace();
+            match self.advance() {
+                Some(',') => {}
+                Some(']') => return Ok(Json::Array(items)),
+                _ => return Err(self.error("Expected ',' or ']' in array.")),
+            }
+        }
+    }
+
+    fn object(&mut self) -> Result<Json, JsonError> {
+        self.consume('{')?;
+        let mut members = Vec::new();
+        self.skip_whitespace();
+        if self.peek() == Some('}') {
+            self.current += 1;
+            return Ok(Json::Object(members));
+        }
+        loop {
+            self.skip_whitespace();
+            if self.peek() != Some('"') {
+                return Err(self.error("Expected a string key in object."));
+            }
+            let key = self.string()?;
+            self.consume(':')?;
+            members.push((key, self.value()?));
+            self.skip_whitespace();
+            match self.advance() {
+                Some(',') => {}
+                Some('}') => return Ok(Json::Object(members)),
+                _ => return Err(self.error("Expected ',' or '}' in object.")),
+            }
+        }
+    }
+
+    fn number(&mut self) -> Result<Json, JsonError> {
+        let start = self.current;
+        while let Some('-' | '+' | '.' | 'e' | 'E' | '0'..='9') = self.peek() {
+            self.current += 1;
+        }
+        let text: String = self.source[start..self.current].iter().collect();
+        text.parse()
+            .map(Json::Number)
+            .map_err(|_| self.error(&format!("Invalid number '{text}'.")))
+    }
+
+    fn string(&mut self) -> Result<String, JsonError> {
+        self.consume('"')?;
+        let mut value = String::new();
+        loop {
+            match self.advance() {
+                Some('"') => return Ok(value),
+                Some('\\') => match self.advance() {
+                    Some('"') => value.push('"'),
+                    Some('\\') => value.push('\\'),
+                    Some('/') => value.push('/'),
+                    Some('b') => value.push('\u{8}'),
+                    Some('f') => value.push('\u{c}'),
+                    Some('n') => value.push('\n'),
+                    Some('r') => value.push('\r'),
+                    Some('t') => value.push('\t'),
+                    Some('u') => value.push(self.unicode_escape()?),
+                    _ => return Err(self.error("Invalid escape in string.")),
+                },
+                Some(ch) => value.push(ch),
+                None => return Err(self.error("Unterminated string.")),
+            }
+        }
+    }
+
+    fn unicode_escape(&mut self) -> Result<char, JsonError> {
+        let high = self.hex4()?;
+        let code = if (0xD800..0xDC00).contains(&high) {
+            // A surrogate pair spells one character outside the BMP.
+            if self.advance() != Some('\\') || self.advance() != Some('u') {
+                return Err(self.error("Expected a low surrogate."));
+            }
+            let low = self.hex4()?;
+            if !(0xDC00..=0xDFFF).contains(&low) {
+                return Err(self.error("Expected a low surrogate."));
+            }
+            0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00)
+        } else {
+            high
+        };
+        char::from_u32(code).ok_or_else(|| self.error("Invalid unicode escape."))
+    }
+
+    fn hex4(&mut self) -> Result<u32, JsonError> {
+        let mut code = 0;
+        for _ in 0..4 {
+            let digit = self
+                .advance()
+                .and_then(|ch| ch.to_digit(16))
+                .ok_or_else(|| self.error("Invalid unicode escape."))?;
+            code = code * 16 + digit;
+        }
+        Ok(code)
+    }
+}
+
+impl ToJson for Token {
+    fn to_json(&self) -> Json {
+        Json::node(
+            "Token",
+            vec![
+                ("type", Json::String(format!("{:?}", self.ttype()))),
+                ("lexeme", Json::String(self.lexeme())),
+                ("literal", self.literal().to_json()),
+                ("line", Json::Number(self.line() as f64)),
+                ("start", Json::Number(self.span().start as f64)),
+                ("end", Json::Number(self.span().end as f64)),
+            ],
+        )
+    }
+}
+
+impl FromJson for Token {
+    fn from_json(json: &Json) -> Result<Self, JsonError> {
+        let name = String::from_json(json.field("type")?)?;
+        let ttype = TokenType::ALL
+            .iter()
+            .find(|t| format!("{t:?}") == name)
+            .copied()
+            .ok_or_else(|| JsonError::new(format!("Unknown token type '{name}'.")))?;
+        let mut token = Token::new(
+            ttype,
+            String::from_json(json.field("lexeme")?)?,
+            Option::from_json(json.field("literal")?)?,
+            position(json, "line")?,
+        );
+        token.set_span(Span {
+            start: position(json, "start")?,
+            end: position(json, "end")?,
+        });
+        Ok(token)
+    }
+}
+
+/// A token's line or offset, which must be a whole number.
+fn position(json: &Json, name: &str) -> Result<usize, JsonError> {
+    match json.field(name)? {
+        Json::Number(n) if n.fract() == 0.0 && *n >= 0.0 => Ok(*n as usize),
+        _ => Err(JsonError::new(format!("Token '{name}' must be a number."))),
+    }
+}
+
+// Literals are tagged objects rather than bare JSON values so that
+// `Literal::Nil` stays distinct from an absent literal. JSON has no
+// infinities or NaN, so those numbers are written as the strings "inf",
+// "-inf" and "NaN".
+impl ToJson for Literal {
+    fn to_json(&self) -> Json {
+        match self {
+            Literal::Num(n) if !n.is_finite() => {
+                Json::node("Num", vec![("value", Json::String(n.to_string()))])
+            }
+            Literal::Num(n) => Json::node("Num", vec![("value", Json::Number(*n))]),
+            Literal::Str(s) => Json::node("Str", vec![("value", Json::String(s.clone()))]),
+            Literal::Bool(b) => Json::node("Bool", vec![("value", Json::Bool(*b))]),
+            Literal::Nil => Json::node("Nil", Vec::new()),
+            // Only the interpreter produces this, and never into a tree.
+            Literal::ArithmeticError => unreachable!("arithmetic errors are not literals"),
+        }
+    }
+}
+
+impl FromJson for Literal {
+    fn from_json(json: &Json) -> Result<Self, JsonError> {
+        match (json.kind()?, json.field("value")) {
+            ("Num", Ok(Json::Number(n))) => Ok(Literal::Num(*n)),
+            ("Num", Ok(Json::String(s))) if matches!(s.as_str(), "inf" | "-inf" | "NaN") => {
+                Ok(Literal::Num(s.parse().unwrap()))
+            }
+            ("Str", Ok(Json::String(s))) => Ok(Literal::Str(s.clone())),
+            ("Bool", Ok(Json::Bool(b))) => Ok(Literal::Bool(*b)),
+            ("Nil", _) => Ok(Literal::Nil),
+            (kind, _) => Err(JsonError::new(format!("Invalid literal of kind '{kind}'."))),
+        }
+    }
+}
+
+impl ToJson for bool {
+    fn to_json(&self) -> Json {
+        Json::Bool(*self)
+    }
+}
+
+impl FromJson for bool {
+    fn from_json(json: &Json) -> Result<Self, JsonError> {
+        match json {
+            Json::Bool(b) => Ok(*b),
+            _ => Err(JsonError::new("Expected a boolean.".to_string())),
+        }
+    }
+}
+
+impl ToJson for String {
+    fn to_json(&self) -> Json {
+        Json::String(self.clone())
+    }
+}
+
+impl FromJson for String {
+    fn from_json(json: &Json) -> Result<Self, JsonError> {
+        match json {
+            Json::String(s) => Ok(s.clone()),
+            _ => Err(JsonError::new("Expected a string.".to_string())),
+        }
+    }
+}
+
+impl<T: ToJson> ToJson for Rc<T> {
+    fn to_json(&self) -> Json {
+        (**self).to_json()
+    }
+}
+
+impl<T: FromJson> FromJson for Rc<T> {
+    fn from_json(json: &Json) -> Result<Self, JsonError> {
+        Ok(Rc::new(T::from_json(json)?))
+    }
+}
+
+impl<T: ToJson> ToJson for Option<T> {
+    fn to_json(&self) -> Json {
+        match self {
+            Some(value) => value.to_json(),
+            None => Json::Null,
+        }
+    }
+}
+
+impl<T: FromJson> FromJson for Option<T> {
+    fn from_json(json: &Json) -> Result<Self, JsonError> {
+        match json {
+            Json::Null => Ok(None),
+            _ => Ok(Some(T::from_json(json)?)),
+        }
+    }
+}
+
+impl<T: ToJson> ToJson for [T] {
+    fn to_json(&self) -> Json {
+        Json::Array(self.iter().map(|item| item.to_json()).collect())
+    }
+}
+
+impl<T: ToJson> ToJson for Vec<T> {
+    fn to_json(&self) -> Json {
+        self.as_slice().to_json()
+    }
+}
+
+impl<T: FromJson> FromJson for Vec<T> {
+    fn from_json(json: &Json) -> Result<Self, JsonError> {
+        match json {
+            Json::Array(items) => items.iter().map(T::from_json).collect(),
+            _ => Err(JsonError::new("Expected an array.".to_string())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::*;
+    use crate::interpreter::*;
+    use crate::parser::*;
+    use crate::scanner::*;
+    use crate::structural_eq::*;
+
+    // One program per node kind and then some, in the spirit of
+    // grammar/example.txt.
+    const CORPUS: &[&str] = &[
+        "print 1 + 2 * 3 - 4 / 5 % 6;",
+        "print (1 + 2) ** 3 ** 2;",
+        "print !true == false != nil;",
+        "print 1 < 2 == 3 >= 4 > 5 <= 6;",
+        "print 5 & 3 | 8 ^ 2 << 1 >> 1;",
+        "print ~1, -2, 3;",
+        "print \"a string\twith\ncontrol characters\\\";",
+        "print \"unicode: \u{e9}\u{1F600}\";",
+        "var a;\nvar b = nil;\nvar c = a ? b : 1.5;",
+        "var x = 1;\nx = x + 1;\nx += 2;\nx -= 1;\nx *= 3;\nx /= 2;\nx %= 4;",
+        "var i = 0;\nprint i++;\nprint ++i;\nprint i--;\nprint --i;",
+        "var big = 123456789012345678901234567890;\nprint big * 0.000001;",
+        "/* comments */ var s = \"multi\nline\"; // trailing\nprint s;",
+        "/// Documented.\nvar d = 1;",
+        "print argc(), arg(0);",
+        "test \"named\" {\n    assert 1 < 2;\n    assert_eq(1, 1, \"same\");\n}",
+    ];
+
+    fn parse(source: &str) -> Vec<Rc<Stmt>> {
+        let mut scanner = Scanner::new(source.to_string());
+        let tokens = scanner.scan_tokens().unwrap();
+        Parser::new(tokens).parse().unwrap()
+    }
+
+    #[test]
+    fn test_round_trip() {
+        for source in CORPUS {
+            let statements = parse(source);
+            let json = program_to_json(&statements);
+            let loaded = program_from_json(&json).unwrap();
+            assert!(statements.structural_eq(&loaded), "{source}");
+            // Lines and offsets are not part of structural equality, so
+            // compare the exports too.
+            assert_eq!(json, program_to_json(&loaded), "{source}");
+        }
+    }
+
+    #[test]
+    fn test_non_finite_numbers() {
+        for n in [f64::INFINITY, f64::NEG_INFINITY, f64::NAN] {
+            let json = Literal::Num(n).to_json().to_string();
+            assert_eq!(json, format!("{{\"kind\":\"Num\",\"value\":\"{n}\"}}"));
+            let loaded = Literal::from_json(&Json::parse(&json).unwrap()).unwrap();
+            assert_eq!(loaded.to_string(), Literal::Num(n).to_string());
+        }
+
+        // Too many digits for an f64 scans as infinity.
+        let source = format!("print 1{};", "0".repeat(400));
+        let json = program_to_json(&parse(&source));
+        assert!(json.contains("\"value\":\"inf\""), "{json}");
+        let loaded = program_from_json(&json).unwrap();
+        assert!(parse(&source).structural_eq(&loaded));
+    }
+
+    #[test]
+    fn test_export_shape() {
+        let json = program_to_json(&parse("print -x;"));
+        assert_eq!(
+            json,
+            "[{\"kind\":\"Print\",\"expression\":{\"kind\":\"Unary\",\
+             \"operator\":{\"kind\":\"Token\",\"type\":\"Minus\",\"lexeme\":\"-\",\
+             \"literal\":null,\"line\":1,\"start\":6,\"end\":7},\
+             \"right\":{\"kind\":\"Variable\",\"name\":{\"kind\":\"Token\",\
+             \"type\":\"Identifier\",\"lexeme\":\"x\",\"literal\":null,\"line\":1,\
+             \"start\":7,\"end\":8}}}}]"
+        );
+    }
+
+    #[test]
+    fn test_interpret_loaded_program() {
+        let json = program_to_json(&parse("var a = 2;\na *= 3;\nvar b = a++ + 1;"));
+        let loaded = program_from_json(&json).unwrap();
+        let mut terp = Interpreter::new();
+        assert!(terp.interpret(&loaded).is_ok());
+        let loaded = program_from_json(&program_to_json(&parse("a + b;"))).unwrap();
+        assert!(terp.interpret(&loaded).is_ok());
+    }
+
+    #[test]
+    fn test_interpret_literal_without_value() {
+        let json = program_to_json(&parse("print 1;"))
+            .replace("\"value\":{\"kind\":\"Num\",\"value\":1}", "\"value\":null");
+        let loaded = program_from_json(&json).unwrap();
+        let (result, errors) = capture_errors(|| Interpreter::new().interpret(&loaded));
+        assert!(result.is_err());
+        assert_eq!(errors, ["[line 1] Error: Literal has no value."]);
+    }
+
+    #[test]
+    fn test_parse_json() {
+        let json = Json::parse(" { \"a\" : [1, -2.5e3, true, null, \"\\u00e9\\ud83d\\ude00\"] } ")
+            .unwrap();
+        assert_eq!(
+            json,
+            Json::Object(vec![(
+                "a".to_string(),
+                Json::Array(vec![
+                    Json::Number(1.0),
+                    Json::Number(-2500.0),
+                    Json::Bool(true),
+                    Json::Null,
+                    Json::String("\u{e9}\u{1F600}".to_string()),
+                ])
+            )])
+        );
+        assert_eq!(Json::parse(&json.to_string()).unwrap(), json);
+    }
+
+    #[test]
+    fn test_invalid_surrogates() {
+        let error = |source: &str| Json::parse(source).unwrap_err().to_string();
+        assert_eq!(
+            error("\"\\uD800\\u0041\""),
+            "Expected a low surrogate. (at character 13)"
+        );
+        assert_eq!(
+            error("\"\\uD83D\\uD83D\""),
+            "Expected a low surrogate. (at character 13)"
+        );
+        assert_eq!(
+            error("\"\\uD800x\""),
+            "Expected a low surrogate. (at character 8)"
+        );
+        // A lone low surrogate is not a character either.
+        assert_eq!(
+            error("\"\\uDC00\""),
+            "Invalid unicode escape. (at character 7)"
+        );
+    }
+
+    #[test]
+    fn test_load_errors() {
+        let error = |source: &str| program_from_json(source).unwrap_err().to_string();
+        assert_eq!(error("{}"), "Expected an array.");
+        assert_eq!(error("[{\"kind\":\"Loop\"}]"), "Unknown Stmt kind 'Loop'.");
+        assert_eq!(
+            error("[{\"kind\":\"Print\"}]"),
+            "Missing field 'expression'."
+        );
+        assert_eq!(
+            error(
+                "[{\"kind\":\"Print\",\"expression\":{\"kind\":\"Variable\",\"name\":\
+                 {\"kind\":\"Token\",\"type\":\"Identifier\",\"lexeme\":\"x\",\
+                 \"literal\":null,\"line\":1,\"start\":-1,\"end\":0}}}]"
+            ),
+            "Token 'start' must be a number."
+        );
+        assert_eq!(error("[1,]"), "Expected a JSON value. (at character 3)");
+        assert_eq!(
+            error("[] x"),
+            "Unexpected data after JSON value. (at character 3)"
+        );
+
+        let literal = Json::parse("{\"kind\":\"ArithmeticError\"}").unwrap();
+        assert_eq!(
+            Literal::from_json(&literal).unwrap_err().to_string(),
+            "Invalid literal of kind 'ArithmeticError'."
+        );
+    }
+}
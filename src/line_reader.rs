@@ -0,0 +1,210 @@
+use std::cell::RefCell;
+use std::io::{self, stdout, BufRead, IsTerminal, Write};
+use std::path::PathBuf;
+use std::rc::Rc;
+
+use rustyline::completion::Completer;
+use rustyline::error::ReadlineError;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::history::DefaultHistory;
+use rustyline::validate::Validator;
+use rustyline::{Context, Editor, Helper};
+
+use crate::scanner::*;
+
+const HISTORY_FILE: &str = ".jialox_history";
+
+pub enum Input {
+    Line(String),
+    /// Ctrl+C: drop whatever has been typed so far.
+    Cancel,
+    End,
+}
+
+/// Reads REPL input: with line editing, history (Ctrl+R searches it) and
+/// tab completion on a terminal, or plain lines when stdin is piped.
+pub enum LineReader {
+    Editor {
+        editor: Box<Editor<Completions, DefaultHistory>>,
+        history: Option<PathBuf>,
+        globals: Rc<RefCell<Vec<String>>>,
+    },
+    Plain,
+}
+
+impl LineReader {
+    pub fn new() -> LineReader {
+        if !io::stdin().is_terminal() {
+            return LineReader::Plain;
+        }
+        let Ok(mut editor) = Editor::new() else {
+            return LineReader::Plain;
+        };
+        let globals = Rc::new(RefCell::new(Vec::new()));
+        editor.set_helper(Some(Completions {
+            keywords: Scanner::keywords(),
+            globals: Rc::clone(&globals),
+        }));
+        let history = home_dir().map(|home| home.join(HISTORY_FILE));
+        if let Some(path) = &history {
+            // A missing file just means a first session.
+            let _ = editor.load_history(path);
+        }
+        LineReader::Editor {
+            editor: Box::new(editor),
+            history,
+            globals,
+        }
+    }
+
+    /// The next line, without its newline.
+    pub fn read_line(&mut self, prompt: &str) -> Input {
+        match self {
+            LineReader::Editor { editor, .. } => match editor.readline(prompt) {
+                Ok(line) => Input::Line(line),
+                Err(ReadlineError::Interrupted) => Input::Cancel,
+                Err(_) => Input::End,
+            },
+            LineReader::Plain => {
+                print!("{prompt}");
+                stdout().flush().unwrap();
+                let mut line = String::new();
+                match io::stdin().lock().read_line(&mut line) {
+                    Ok(0) | Err(_) => Input::End,
+                    Ok(_) => {
+                        let end = line.trim_end_matches(['\n', '\r']).len();
+                        line.truncate(end);
+                        Input::Line(line)
+                    }
+                }
+            }
+        }
+    }
+
+    /// Records a complete input in the history file.
+    pub fn add_history(&mut self, entry: &str) {
+        if let LineReader::Editor {
+            editor, history, ..
+        } = self
+        {
+            let _ = editor.add_history_entry(entry);
+            if let Some(path) = history {
+                let _ = editor.save_history(path);
+            }
+        }
+    }
+
+    /// The global names offered by tab completion alongside the keywords.
+    pub fn set_globals(&mut self, names: Vec<String>) {
+        if let LineReader::Editor { globals, .. } = self {
+            *globals.borrow_mut() = names;
+        }
+    }
+}
+
+fn home_dir() -> Option<PathBuf> {
+    std::env::var_os("HOME")
+        .or_else(|| std::env::var_os("USERPROFILE"))
+        .map(PathBuf::from)
+}
+
+pub struct Completions {
+    keywords: Vec<String>,
+    globals: Rc<RefCell<Vec<String>>>,
+}
+
+impl Completions {
+    /// Keywords and globals starting with the word before `pos`, and where
+    /// that word starts.
+    fn candidates(&self, line: &str, pos: usize) -> (usize, Vec<String>) {
+        let start = line[..pos]
+            .char_indices()
+            .rev()
+            .find(|&(_, c)| !(c.is_ascii_alphanumeric() || c == '_'))
+            .map_or(0, |(i, c)| i + c.len_utf8());
+        let prefix = &line[start..pos];
+        if prefix.is_empty() {
+            return (start, Vec::new());
+        }
+        let globals = self.globals.borrow();
+        let mut matches: Vec<String> = self
+            .keywords
+            .iter()
+            .chain(globals.iter())
+            .filter(|name| name.starts_with(prefix))
+            .cloned()
+            .collect();
+        matches.sort();
+        matches.dedup();
+        (start, matches)
+    }
+}
+
+impl Completer for Completions {
+    type Candidate = String;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<String>)> {
+        Ok(self.candidates(line, pos))
+    }
+}
+
+impl Hinter for Completions {
+    type Hint = String;
+}
+
+impl Highlighter for Completions {}
+
+impl Validator for Completions {}
+
+impl Helper for Completions {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_completion_candidates() {
+        let completions = Completions {
+            keywords: Scanner::keywords(),
+            globals: Rc::new(RefCell::new(vec![
+                "value".to_string(),
+                "variance".to_string(),
+            ])),
+        };
+        assert_eq!(
+            completions.candidates("print va", 8),
+            (
+                6,
+                vec![
+                    "value".to_string(),
+                    "var".to_string(),
+                    "variance".to_string()
+                ]
+            )
+        );
+        assert_eq!(
+            completions.candidates("x = wh + 1", 6),
+            (4, vec!["while".to_string()])
+        );
+        assert_eq!(completions.candidates("print ", 6), (6, Vec::new()));
+
+        // Positions are byte offsets, and the separator may be wider than
+        // one byte.
+        let line = "print \"caf\u{e9}";
+        assert_eq!(
+            completions.candidates(line, line.len()),
+            (line.len(), Vec::new())
+        );
+        let line = "\"\u{e9}\"\u{e9}wh";
+        assert_eq!(
+            completions.candidates(line, line.len()),
+            (line.len() - 2, vec!["while".to_string()])
+        );
+    }
+}
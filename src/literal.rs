@@ -9,6 +9,14 @@ pub enum Literal {
     ArithmeticError,
 }
 
+/// A value as the REPL and failed assertions show it: strings in quotes.
+pub fn show(value: &Literal) -> String {
+    match value {
+        Literal::Str(s) => format!("\"{s}\""),
+        _ => value.to_string(),
+    }
+}
+
 impl fmt::Display for Literal {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
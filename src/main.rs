@@ -1,10 +1,13 @@
 use std::env::args;
-use std::io::{self, stdout, BufRead, Write};
+use std::io::{self, IsTerminal, Read, Write};
+use std::rc::Rc;
+use std::time::{Duration, Instant, SystemTime};
 
 mod error;
 use error::*;
 
 mod stmt;
+use stmt::*;
 
 mod token_type;
 // use token_type::*;
@@ -16,35 +19,392 @@ mod scanner;
 use scanner::*;
 
 mod literal;
+use literal::*;
+
+mod structural_eq;
+
+mod json;
+use json::*;
+
+mod dot;
+use dot::*;
+
+mod formatter;
+use formatter::*;
+
+mod doc;
+use doc::*;
 
 mod expr;
+
+mod arena;
 // use expr::*;
 
 mod parser;
 use parser::*;
 
+mod environment;
+
 mod interpreter;
 use interpreter::*;
 
+mod ast_printer;
+use ast_printer::*;
+
+mod line_reader;
+use line_reader::*;
+
+mod test_runner;
+use test_runner::*;
+
+#[cfg(test)]
+mod golden;
+
+#[cfg(test)]
+mod arena_tests;
+
+#[cfg(test)]
+mod visitor_tests;
+
+#[cfg(test)]
+#[path = "../generate_ast/mod.rs"]
+mod generate_ast;
+
 fn main() {
-    let args: Vec<String> = args().collect();
-    let jialox = Jialox::new();
-
-    if args.len() > 2 {
-        println!("Usage: jialox [file_path]");
-        std::process::exit(64);
-    } else if args.len() == 2 {
-        jialox.run_file(&args[1]).expect("Could not run file");
+    let args: Vec<String> = args().skip(1).collect();
+    if args.first().map(String::as_str) == Some("fmt") {
+        format_files(&args[1..]);
+    }
+    if args.first().map(String::as_str) == Some("doc") {
+        document_files(&args[1..]);
+    }
+    if args.first().map(String::as_str) == Some("watch") {
+        watch_file(&args[1..]);
+    }
+    if args.first().map(String::as_str) == Some("test") {
+        test_files(&args[1..]);
+    }
+
+    let mut jialox = Jialox::new();
+    let mut args = args.into_iter().peekable();
+    // `run` only spells out what a bare script path already means.
+    let explicit_run = args.next_if(|arg| arg == "run").is_some();
+    let mut script = None;
+    while let Some(arg) = args.next() {
+        if arg == "--print-ast" || arg == "--ast" {
+            jialox.print_ast = Some(PrintMode::Lisp);
+        } else if let Some(name) = arg
+            .strip_prefix("--print-ast=")
+            .or_else(|| arg.strip_prefix("--ast="))
+        {
+            match PrintMode::from_name(name) {
+                Some(mode) => jialox.print_ast = Some(mode),
+                None => usage(),
+            }
+        } else if let Some(name) = arg.strip_prefix("--emit=") {
+            match name {
+                "json" => jialox.emit = Some(Emit::Json),
+                "dot" => jialox.emit = Some(Emit::Dot),
+                _ => usage(),
+            }
+        } else if arg == "--from-json" {
+            jialox.from_json = true;
+        } else if arg == "--tokens" {
+            jialox.print_tokens = true;
+        } else if arg == "--quiet" || arg == "-q" {
+            jialox.quiet = true;
+        } else if arg == "--version" || arg == "-V" {
+            println!("Jialox {}", jialox.version);
+            std::process::exit(0);
+        } else if arg == "--help" || arg == "-h" {
+            usage();
+        } else if arg == "-e" {
+            match args.next() {
+                Some(code) => script = Some(Script::Code(code)),
+                None => usage(),
+            }
+            break;
+        } else if arg == "-" {
+            script = Some(Script::Stdin);
+            break;
+        } else if arg.starts_with('-') {
+            usage();
+        } else {
+            script = Some(Script::File(arg));
+            break;
+        }
+    }
+    // Everything after the script belongs to it.
+    let script_args: Vec<String> = args.collect();
+
+    match script {
+        Some(script) => jialox.run_script(script, script_args),
+        None if explicit_run || jialox.from_json => usage(),
+        None => jialox.run_prompt(),
+    }
+}
+
+fn usage() -> ! {
+    println!("Usage: jialox [options] [file_path [args...]]");
+    println!("       jialox run [options] file_path [args...]");
+    println!("       jialox [options] -e code [args...]");
+    println!("       jialox [options] - [args...]");
+    println!("       jialox fmt [--check] file_path...");
+    println!("       jialox doc [--html] file_path...");
+    println!("       jialox watch file_path [args...]");
+    println!("       jialox test file_path...");
+    println!();
+    println!("fmt, doc, watch and test must be the first argument.");
+    println!();
+    println!("Options:");
+    println!("  -e code                     run code given on the command line");
+    println!("  -                           read the script from stdin");
+    println!("  -q, --quiet                 do not print the banner");
+    println!("  -V, --version               print the version and exit");
+    println!("  --tokens                    print the tokens before running");
+    println!("  --ast[=lisp|rpn|tree]       print the syntax tree before running");
+    println!("  --print-ast[=lisp|rpn|tree] same as --ast");
+    println!("  --emit=json|dot             print the syntax tree instead of running");
+    println!("  --from-json                 the script is a tree from --emit=json");
+    println!();
+    println!("Scripts read their arguments with argc() and arg(i); arg(0) is the script.");
+    println!("Exit status is 65 for a syntax error, 70 for a runtime error, or the");
+    println!("code passed to exit(code).");
+    std::process::exit(64);
+}
+
+/// Rewrites each file in the canonical layout. With `--check` the files are
+/// left alone and the exit code says whether any of them needed it.
+fn format_files(args: &[String]) -> ! {
+    let check = args.iter().any(|arg| arg == "--check");
+    let paths: Vec<&String> = args.iter().filter(|arg| *arg != "--check").collect();
+    if paths.is_empty() {
+        usage();
+    }
+
+    let mut status = 0;
+    for path in paths {
+        let source = match std::fs::read_to_string(path) {
+            Ok(source) => source,
+            Err(e) => {
+                eprintln!("{path}: {e}");
+                std::process::exit(66);
+            }
+        };
+        let formatted = match format_source(&source) {
+            Ok(formatted) => formatted,
+            // Already reported by the scanner or parser.
+            Err(_) => std::process::exit(65),
+        };
+        if formatted == source {
+            continue;
+        }
+        if check {
+            println!("Would reformat {path}");
+            status = 1;
+        } else if let Err(e) = std::fs::write(path, formatted) {
+            eprintln!("{path}: {e}");
+            std::process::exit(73);
+        }
+    }
+    std::process::exit(status);
+}
+
+/// Runs a script, then runs it again in a fresh interpreter every time the
+/// file changes, until interrupted.
+fn watch_file(args: &[String]) -> ! {
+    let Some((path, script_args)) = args.split_first() else {
+        usage();
+    };
+    let mut watcher = Watcher::new(path);
+    loop {
+        if watcher.changed() {
+            // Clear the screen and move the cursor home.
+            print!("\x1b[2J\x1b[H");
+            let _ = io::stdout().flush();
+            match run_watched(path, script_args, Interpreter::new()) {
+                Ok(status) => {
+                    println!("[exited with status {status}; watching {path} for changes]")
+                }
+                Err(e) => eprintln!("{path}: {e}"),
+            }
+        }
+        std::thread::sleep(WATCH_INTERVAL);
+    }
+}
+
+/// Tells when a file has changed by looking at its modification time.
+struct Watcher {
+    path: String,
+    /// What the last look found; `None` before the first one, and
+    /// `Some(None)` while the file cannot be read.
+    last_modified: Option<Option<SystemTime>>,
+}
+
+impl Watcher {
+    fn new(path: &str) -> Watcher {
+        Watcher {
+            path: path.to_string(),
+            last_modified: None,
+        }
+    }
+
+    /// Whether the file changed since the last call. The first call always
+    /// says it did, so the script runs once at the start.
+    fn changed(&mut self) -> bool {
+        let modified = std::fs::metadata(&self.path)
+            .and_then(|m| m.modified())
+            .ok();
+        self.last_modified.replace(modified) != Some(modified)
+    }
+}
+
+/// Runs the script at `path` in a quiet session of its own, printing to
+/// `interpreter`, and returns the status it exited with.
+fn run_watched(path: &str, args: &[String], interpreter: Interpreter) -> io::Result<i32> {
+    let source = std::fs::read_to_string(path)?;
+    let mut jialox = Jialox {
+        interpreter,
+        quiet: true,
+        ..Jialox::new()
+    };
+    let mut argv = vec![path.to_string()];
+    argv.extend_from_slice(args);
+    jialox.interpreter.set_args(argv);
+    Ok(jialox.run_source(source))
+}
+
+/// Runs the `test` blocks of each script, each on its own, and exits
+/// non-zero if any of them failed.
+fn test_files(args: &[String]) -> ! {
+    if args.is_empty() {
+        usage();
+    }
+
+    let (mut passed, mut failed) = (0, 0);
+    for path in args {
+        let source = match std::fs::read_to_string(path) {
+            Ok(source) => source,
+            Err(e) => {
+                eprintln!("{path}: {e}");
+                std::process::exit(66);
+            }
+        };
+        let mut scanner = Scanner::new(source.clone());
+        let statements = match scanner
+            .scan_tokens()
+            .and_then(|tokens| Parser::new(tokens).parse())
+        {
+            Ok(statements) => statements,
+            // Already reported by the scanner or parser.
+            Err(_) => std::process::exit(65),
+        };
+
+        let outcomes = match run_tests(&statements) {
+            Ok(outcomes) => outcomes,
+            Err(setup) => {
+                println!("test {path}: setup ... FAILED");
+                report_test_failure(path, &source, &setup.failure, &setup.output);
+                failed += 1;
+                continue;
+            }
+        };
+        for outcome in outcomes {
+            let Some(failure) = outcome.failure else {
+                println!("test {path}: {} ... ok", outcome.name);
+                passed += 1;
+                continue;
+            };
+            println!("test {path}: {} ... FAILED", outcome.name);
+            report_test_failure(path, &source, &failure, &outcome.output);
+            failed += 1;
+        }
+    }
+
+    println!();
+    println!("test result: {passed} passed; {failed} failed");
+    std::process::exit(if failed > 0 { 1 } else { 0 });
+}
+
+/// Shows where a test failed, the source line there, and what it printed.
+fn report_test_failure(path: &str, source: &str, (line, message): &(usize, String), output: &str) {
+    println!("    {path}:{line}: {message}");
+    if let Some(text) = line.checked_sub(1).and_then(|i| source.lines().nth(i)) {
+        println!("    {line:>4} | {}", text.trim());
+    }
+    for printed in output.lines() {
+        println!("    output: {printed}");
+    }
+}
+
+/// How often `jialox watch` looks at the file.
+const WATCH_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Where the program to run comes from.
+enum Script {
+    File(String),
+    /// Given with `-e`.
+    Code(String),
+    /// `-`.
+    Stdin,
+}
+
+/// A representation to write to stdout instead of running the program.
+#[derive(Clone, Copy)]
+enum Emit {
+    Json,
+    Dot,
+}
+
+/// Prints API documentation for the given scripts, as Markdown unless
+/// `--html` is given.
+fn document_files(args: &[String]) -> ! {
+    let html = args.iter().any(|arg| arg == "--html");
+    let mut files = Vec::new();
+    for path in args.iter().filter(|arg| *arg != "--html") {
+        match std::fs::read_to_string(path) {
+            Ok(source) => files.push((path.clone(), source)),
+            Err(e) => {
+                eprintln!("{path}: {e}");
+                std::process::exit(66);
+            }
+        }
+    }
+    if files.is_empty() {
+        usage();
+    }
+
+    let format = if html {
+        DocFormat::Html
     } else {
-        jialox.run_prompt();
+        DocFormat::Markdown
+    };
+    match document(&files, format) {
+        Ok(output) => print!("{output}"),
+        // Already reported by the scanner or parser.
+        Err(_) => std::process::exit(65),
     }
+    std::process::exit(0);
 }
 
 struct Jialox {
     version: String,
     authored: String,
     finished_time: String,
-    interpreter: Interpreter
+    interpreter: Interpreter,
+    print_ast: Option<PrintMode>,
+    emit: Option<Emit>,
+    /// The file holds a syntax tree exported with `--emit=json`.
+    from_json: bool,
+    /// Dump the tokens before running, like `print_ast` does for the tree.
+    print_tokens: bool,
+    /// Leave out the banner from `print_basic_info`.
+    quiet: bool,
+    /// The last error came from running the program rather than reading
+    /// it, which scripts report with a different exit code.
+    had_runtime_error: bool,
+    /// Input comes from the prompt, so bare expressions are echoed.
+    repl: bool,
 }
 
 impl Jialox {
@@ -54,57 +414,270 @@ impl Jialox {
             authored: "Jiashu".to_string(),
             finished_time: "June 8 2024".to_string(),
             interpreter: Interpreter::new(),
+            print_ast: None,
+            emit: None,
+            from_json: false,
+            print_tokens: false,
+            quiet: false,
+            had_runtime_error: false,
+            repl: false,
+        }
+    }
+
+    fn run_script(&mut self, script: Script, args: Vec<String>) {
+        let (name, source) = match script {
+            Script::File(path) => match std::fs::read_to_string(&path) {
+                Ok(source) => (path, source),
+                Err(e) => {
+                    eprintln!("{path}: {e}");
+                    std::process::exit(66);
+                }
+            },
+            Script::Code(code) => ("-e".to_string(), code),
+            Script::Stdin => {
+                let mut source = String::new();
+                if let Err(e) = io::stdin().read_to_string(&mut source) {
+                    eprintln!("stdin: {e}");
+                    std::process::exit(66);
+                }
+                ("-".to_string(), source)
+            }
+        };
+        if !io::stdout().is_terminal() {
+            // Nobody watches the lines appear one by one, so write them in
+            // larger chunks.
+            self.interpreter = Interpreter::buffered();
+        }
+        let mut argv = vec![name];
+        argv.extend(args);
+        self.interpreter.set_args(argv);
+        let status = self.run_source(source);
+        if status != 0 {
+            let _ = io::stdout().flush();
+            std::process::exit(status);
         }
     }
 
-    fn run_file(&self, path: &String) -> io::Result<()> {
-        let buf = std::fs::read_to_string(path)?;
-        self.print_basic_info();
-        if self.run(buf).is_err() {
-            // Ignore - error was already reported in run()
-            std::process::exit(65);
+    /// Runs a whole script and returns the status it should exit with.
+    /// Errors have already been reported by then.
+    fn run_source(&mut self, buf: String) -> i32 {
+        if self.emit.is_none() && !self.quiet {
+            self.print_basic_info();
+        }
+        let result = if self.from_json {
+            match program_from_json(&buf) {
+                Ok(statements) => self.run_statements(&statements),
+                Err(e) => {
+                    eprintln!("Error: {e}");
+                    return 65;
+                }
+            }
+        } else {
+            self.run(buf)
+        };
+        match result {
+            Ok(()) => 0,
+            Err(e) => match e.exit_code() {
+                Some(code) => code,
+                None if self.had_runtime_error => 70,
+                None => 65,
+            },
         }
-        Ok(())
     }
     
-    fn run_prompt(&self) {
-        let stdin = io::stdin();
-        self.print_basic_info();
-        println!("(Press <Ctrl+z> to exit normally)");
-        self.start_input_line();
-        for line in stdin.lock().lines() {
-            if let Ok(line) = line {
-                if line.is_empty() {
-                    self.start_input_line();
+    fn run_prompt(&mut self) {
+        self.repl = true;
+        if !self.quiet {
+            self.print_basic_info();
+            println!("(Type :help for commands, :quit or Ctrl+D to exit)");
+        }
+        let mut reader = LineReader::new();
+        let mut buffer = String::new();
+        loop {
+            let prompt = if buffer.is_empty() { ">>> " } else { "... " };
+            let line = match reader.read_line(prompt) {
+                Input::Line(line) => line,
+                Input::Cancel => {
+                    buffer.clear();
                     continue;
                 }
-                if self.run(line).is_err() {
-                    // Ignore - error was already reported in run()
+                Input::End => break,
+            };
+            if buffer.is_empty() && line.is_empty() {
+                continue;
+            }
+            if buffer.is_empty() && line.starts_with(':') {
+                reader.add_history(&line);
+                // Stop too once stdout is gone.
+                if !self.run_command(&line, &mut io::stdout()).unwrap_or(false) {
+                    break;
                 }
-            } else {
-                break;
+                reader.set_globals(self.global_names());
+                continue;
+            }
+            if !buffer.is_empty() {
+                buffer.push('\n');
+            }
+            buffer.push_str(&line);
+            // An empty line gives up on an unfinished statement and runs it,
+            // errors and all.
+            if !line.is_empty() && is_incomplete(&buffer) {
+                continue;
             }
-            self.start_input_line();
+            reader.add_history(&buffer);
+            if self.run(std::mem::take(&mut buffer)).is_err() {
+                // Ignore - error was already reported in run()
+            }
+            reader.set_globals(self.global_names());
         }
     }
-    
-    fn run(&self, source: String) -> Result<(), JialoxError> {
+
+    /// Runs a `:command` typed at the prompt, writing what it shows to
+    /// `out`. Returns false for `:quit`.
+    fn run_command(&mut self, line: &str, out: &mut dyn Write) -> io::Result<bool> {
+        let (command, argument) = match line.split_once(char::is_whitespace) {
+            Some((command, argument)) => (command, argument.trim()),
+            None => (line, ""),
+        };
+        match command {
+            ":help" => {
+                writeln!(out, ":help          show this list")?;
+                writeln!(out, ":load FILE     run a script in this session")?;
+                writeln!(out, ":reset         forget all variables")?;
+                writeln!(out, ":env           list global variables")?;
+                writeln!(out, ":ast CODE      show the syntax tree of CODE")?;
+                writeln!(out, ":tokens CODE   show the tokens of CODE")?;
+                writeln!(out, ":time CODE     run CODE and show how long it took")?;
+                writeln!(out, ":quit          leave the REPL")?;
+            }
+            ":load" => match std::fs::read_to_string(argument) {
+                Ok(source) => {
+                    // Scripts need their semicolons even here.
+                    let result = self.with_repl(false, |jialox| jialox.run(source));
+                    // Back at the prompt, an exit() in the script ends the
+                    // session.
+                    if let Err(e) = result {
+                        if e.exit_code().is_some() {
+                            self.runtime_error(e);
+                        }
+                    }
+                }
+                Err(e) => eprintln!("Could not read '{argument}': {e}"),
+            },
+            ":reset" => {
+                self.interpreter.reset();
+                writeln!(out, "Session reset.")?;
+            }
+            ":env" => {
+                for (name, value) in self.interpreter.globals() {
+                    writeln!(out, "{name} = {}", show(&value))?;
+                }
+            }
+            ":ast" => {
+                let mut scanner = Scanner::new(argument.to_string());
+                if let Ok(tokens) = scanner.scan_tokens() {
+                    if let Ok(statements) = Parser::repl(tokens).parse() {
+                        let mode = self.print_ast.unwrap_or(PrintMode::Lisp);
+                        if let Ok(tree) = AstPrinter::new(mode).print_program(&statements) {
+                            writeln!(out, "{tree}")?;
+                        }
+                    }
+                }
+            }
+            ":tokens" => {
+                let mut scanner = Scanner::new(argument.to_string());
+                if let Ok(tokens) = scanner.scan_tokens() {
+                    for token in tokens {
+                        writeln!(out, "{token}")?;
+                    }
+                }
+            }
+            ":time" => {
+                let start = Instant::now();
+                let _ = self.run(argument.to_string());
+                writeln!(out, "(took {:?})", start.elapsed())?;
+            }
+            ":quit" | ":q" => return Ok(false),
+            _ => eprintln!("Unknown command '{command}'. Type :help for a list."),
+        }
+        Ok(true)
+    }
+
+    /// Runs `f` with `repl` set as given, and puts the old setting back
+    /// however `f` returns.
+    fn with_repl<T>(&mut self, repl: bool, f: impl FnOnce(&mut Jialox) -> T) -> T {
+        let saved = std::mem::replace(&mut self.repl, repl);
+        let result = f(self);
+        self.repl = saved;
+        result
+    }
+
+    fn global_names(&self) -> Vec<String> {
+        let globals = self.interpreter.globals();
+        globals.into_iter().map(|(name, _)| name).collect()
+    }
+
+    fn run(&mut self, source: String) -> Result<(), JialoxError> {
         let mut scanner = Scanner::new(source);
         let tokens = scanner.scan_tokens()?;
-        let mut parser = Parser::new(tokens);
+        if self.print_tokens {
+            for token in tokens {
+                println!("{token}");
+            }
+        }
+        let mut parser = if self.repl {
+            Parser::repl(tokens)
+        } else {
+            Parser::new(tokens)
+        };
         let statements = parser.parse()?;
-        if let Err(e) = self.interpreter.interpret(&statements) {
-            e.report("");
-            return Err(e);
+        self.run_statements(&statements)
+    }
+
+    fn run_statements(&mut self, statements: &[Rc<Stmt>]) -> Result<(), JialoxError> {
+        if let Some(mode) = self.print_ast {
+            println!("{}", AstPrinter::new(mode).print_program(statements)?);
+        }
+        match self.emit {
+            Some(Emit::Json) => {
+                println!("{}", program_to_json(statements));
+                return Ok(());
+            }
+            Some(Emit::Dot) => {
+                print!("{}", DotPrinter::new().print_program(statements)?);
+                return Ok(());
+            }
+            None => {}
+        }
+        if !self.repl {
+            if let Err(e) = self.interpreter.interpret(statements) {
+                return Err(self.runtime_error(e));
+            }
+            return Ok(());
+        }
+        match self.interpreter.interpret_repl(statements) {
+            Ok(Some(value)) => println!("{}", show(&value)),
+            Ok(None) => {}
+            Err(e) => return Err(self.runtime_error(e)),
         }
         Ok(())
     }
-    
-    fn start_input_line(&self) {
-        print!(">>> ");
-        stdout().flush().unwrap();
+
+    /// Notes that running the program failed. The error itself was
+    /// reported when it was made. A call to `exit()` at the prompt ends the
+    /// session; scripts hand the code back from `run_source`.
+    fn runtime_error(&mut self, e: JialoxError) -> JialoxError {
+        match e.exit_code() {
+            Some(code) if self.repl => {
+                let _ = io::stdout().flush();
+                std::process::exit(code);
+            }
+            Some(_) => {}
+            None => self.had_runtime_error = true,
+        }
+        e
     }
-    
+
     fn print_basic_info(&self) {
         println!(
             "Jialox {} | Authored by {} | Finished in {}",
@@ -114,4 +687,136 @@ impl Jialox {
         );
     }
     
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A session as at the prompt, whose programs print into `buffer`.
+    fn session(buffer: &SharedBuffer) -> Jialox {
+        let mut jialox = Jialox::new();
+        jialox.interpreter = Interpreter::with_output(Box::new(buffer.clone()));
+        jialox.repl = true;
+        jialox
+    }
+
+    fn command(jialox: &mut Jialox, line: &str) -> (bool, String) {
+        let mut out = Vec::new();
+        let going_on = jialox.run_command(line, &mut out).unwrap();
+        (going_on, String::from_utf8(out).unwrap())
+    }
+
+    fn temp_script(name: &str, source: &str) -> String {
+        let path = std::env::temp_dir().join(format!("jialox-{}-{name}", std::process::id()));
+        std::fs::write(&path, source).unwrap();
+        path.to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn test_watcher_notices_changes() {
+        let path = temp_script("watched.jlx", "print 1;\n");
+        let mut watcher = Watcher::new(&path);
+        assert!(watcher.changed());
+        assert!(!watcher.changed());
+
+        let file = std::fs::File::options().write(true).open(&path).unwrap();
+        file.set_modified(SystemTime::now() + Duration::from_secs(10))
+            .unwrap();
+        assert!(watcher.changed());
+        assert!(!watcher.changed());
+
+        std::fs::remove_file(&path).unwrap();
+        assert!(watcher.changed());
+        assert!(!watcher.changed());
+    }
+
+    #[test]
+    fn test_each_watched_run_starts_fresh() {
+        let buffer = SharedBuffer::default();
+        let path = temp_script("rerun.jlx", "var a = arg(1);\nprint a;\n");
+        let args = ["one".to_string()];
+        let interpreter = Interpreter::with_output(Box::new(buffer.clone()));
+        assert_eq!(run_watched(&path, &args, interpreter).unwrap(), 0);
+        assert_eq!(buffer.contents(), "one\n");
+
+        // `a` from the first run is gone.
+        std::fs::write(&path, "print a;\n").unwrap();
+        let interpreter = Interpreter::with_output(Box::new(buffer.clone()));
+        let status = quietly(|| run_watched(&path, &args, interpreter).unwrap());
+        assert_eq!(status, 70);
+
+        std::fs::remove_file(&path).unwrap();
+        let interpreter = Interpreter::with_output(Box::new(buffer.clone()));
+        assert!(run_watched(&path, &args, interpreter).is_err());
+    }
+
+    #[test]
+    fn test_env_and_reset() {
+        let buffer = SharedBuffer::default();
+        let mut jialox = session(&buffer);
+        jialox
+            .run("var b = \"two\"; var a = 1;".to_string())
+            .unwrap();
+        assert_eq!(
+            command(&mut jialox, ":env"),
+            (true, "a = 1\nb = \"two\"\n".to_string())
+        );
+        assert_eq!(command(&mut jialox, ":reset").1, "Session reset.\n");
+        assert_eq!(command(&mut jialox, ":env").1, "");
+
+        // The session keeps printing where it did before.
+        jialox.run("print 3;".to_string()).unwrap();
+        assert_eq!(buffer.contents(), "3\n");
+    }
+
+    #[test]
+    fn test_load() {
+        let buffer = SharedBuffer::default();
+        let mut jialox = session(&buffer);
+        let path = temp_script("load.jlx", "var x = 2;\nprint x * 3;\n");
+        assert_eq!(
+            command(&mut jialox, &format!(":load {path}")),
+            (true, String::new())
+        );
+        assert_eq!(buffer.contents(), "6\n");
+        assert_eq!(command(&mut jialox, ":env").1, "x = 2\n");
+        assert!(jialox.repl);
+
+        // A script that fails to parse or to run leaves the prompt as it was.
+        std::fs::remove_file(path).unwrap();
+        for (name, source) in [("broken.jlx", "x\n"), ("failing.jlx", "print y;\n")] {
+            let path = temp_script(name, source);
+            quietly(|| command(&mut jialox, &format!(":load {path}")));
+            assert!(jialox.repl, "{source}");
+            std::fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn test_ast_tokens_and_time() {
+        let buffer = SharedBuffer::default();
+        let mut jialox = session(&buffer);
+        assert_eq!(
+            command(&mut jialox, ":ast 1 + 2 * 3").1,
+            "(; (+ 1 (* 2 3)))\n"
+        );
+        assert_eq!(
+            command(&mut jialox, ":tokens a;").1,
+            "Identifier a None 1\nSemicolon ; None 1\nEof  None 1\n"
+        );
+        let (_, timing) = command(&mut jialox, ":time print 1;");
+        assert!(timing.starts_with("(took "), "{timing}");
+        assert_eq!(buffer.contents(), "1\n");
+    }
+
+    #[test]
+    fn test_help_and_quit() {
+        let buffer = SharedBuffer::default();
+        let mut jialox = session(&buffer);
+        let (going_on, help) = command(&mut jialox, ":help");
+        assert!(going_on);
+        assert!(help.lines().all(|line| line.starts_with(':')), "{help}");
+        assert!(!command(&mut jialox, ":quit").0);
+        assert!(!command(&mut jialox, ":q").0);
+    }
+}
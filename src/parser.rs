@@ -1,160 +1,550 @@
 use std::rc::Rc;
 
+use crate::arena::*;
 use crate::error::*;
 use crate::expr::*;
-use crate::stmt::*;
+use crate::scanner::*;
 use crate::token::*;
 use crate::token_type::*;
 
-pub struct Parser<'a> {
+/// Binding power of an operator, from loosest to tightest.
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+enum Precedence {
+    None,
+    Comma,       // ,
+    Assignment,  // = += -= *= /= %=
+    Conditional, // ?:
+    Equality,    // == !=
+    Comparison,  // < > <= >=
+    BitOr,       // |
+    BitXor,      // ^
+    BitAnd,      // &
+    Shift,       // << >>
+    Term,        // + -
+    Factor,      // * / %
+    Unary,       // ! - ~
+    Power,       // **
+    Postfix,     // ++ --
+    Primary,
+}
+
+impl Precedence {
+    /// The next tighter level, used as the right operand's minimum binding
+    /// power for left-associative operators.
+    fn next(self) -> Precedence {
+        match self {
+            Precedence::None => Precedence::Comma,
+            Precedence::Comma => Precedence::Assignment,
+            Precedence::Assignment => Precedence::Conditional,
+            Precedence::Conditional => Precedence::Equality,
+            Precedence::Equality => Precedence::Comparison,
+            Precedence::Comparison => Precedence::BitOr,
+            Precedence::BitOr => Precedence::BitXor,
+            Precedence::BitXor => Precedence::BitAnd,
+            Precedence::BitAnd => Precedence::Shift,
+            Precedence::Shift => Precedence::Term,
+            Precedence::Term => Precedence::Factor,
+            Precedence::Factor => Precedence::Unary,
+            Precedence::Unary => Precedence::Power,
+            Precedence::Power => Precedence::Postfix,
+            Precedence::Postfix | Precedence::Primary => Precedence::Primary,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Associativity {
+    Left,
+    Right,
+}
+
+type PrefixFn<'a, B> = fn(&mut Parser<'a, B>) -> Result<<B as AstBuilder>::Expr, JialoxError>;
+type InfixFn<'a, B> =
+    fn(&mut Parser<'a, B>, <B as AstBuilder>::Expr) -> Result<<B as AstBuilder>::Expr, JialoxError>;
+
+/// One row of the Pratt parser table: how a token behaves at the start of an
+/// expression (`prefix`) and after a complete left operand (`infix`).
+struct ParseRule<'a, B: AssignTargets> {
+    prefix: Option<PrefixFn<'a, B>>,
+    infix: Option<InfixFn<'a, B>>,
+    precedence: Precedence,
+    associativity: Associativity,
+}
+
+impl<'a, B: AssignTargets> ParseRule<'a, B> {
+    fn new(
+        prefix: Option<PrefixFn<'a, B>>,
+        infix: Option<InfixFn<'a, B>>,
+        precedence: Precedence,
+        associativity: Associativity,
+    ) -> ParseRule<'a, B> {
+        ParseRule {
+            prefix,
+            infix,
+            precedence,
+            associativity,
+        }
+    }
+
+    /// The parse table. New operators only need an entry here.
+    fn of(ttype: TokenType) -> ParseRule<'a, B> {
+        use Associativity::*;
+        match ttype {
+            TokenType::LeftParen => ParseRule::new(
+                Some(Parser::grouping),
+                Some(Parser::call),
+                Precedence::Postfix,
+                Left,
+            ),
+            TokenType::Comma => ParseRule::new(None, Some(Parser::binary), Precedence::Comma, Left),
+            TokenType::Equal
+            | TokenType::PlusEqual
+            | TokenType::MinusEqual
+            | TokenType::StarEqual
+            | TokenType::SlashEqual
+            | TokenType::PercentEqual => ParseRule::new(
+                None,
+                Some(Parser::assignment),
+                Precedence::Assignment,
+                Right,
+            ),
+            TokenType::PlusPlus | TokenType::MinusMinus => ParseRule::new(
+                Some(Parser::prefix_update),
+                Some(Parser::postfix_update),
+                Precedence::Postfix,
+                Left,
+            ),
+            TokenType::Question => ParseRule::new(
+                None,
+                Some(Parser::conditional),
+                Precedence::Conditional,
+                Right,
+            ),
+            TokenType::Minus => ParseRule::new(
+                Some(Parser::unary),
+                Some(Parser::binary),
+                Precedence::Term,
+                Left,
+            ),
+            TokenType::Plus => ParseRule::new(None, Some(Parser::binary), Precedence::Term, Left),
+            TokenType::Slash | TokenType::Star | TokenType::Percent => {
+                ParseRule::new(None, Some(Parser::binary), Precedence::Factor, Left)
+            }
+            TokenType::StarStar => {
+                ParseRule::new(None, Some(Parser::binary), Precedence::Power, Right)
+            }
+            TokenType::Bang | TokenType::Tilde => {
+                ParseRule::new(Some(Parser::unary), None, Precedence::None, Left)
+            }
+            TokenType::Pipe => ParseRule::new(None, Some(Parser::binary), Precedence::BitOr, Left),
+            TokenType::Caret => {
+                ParseRule::new(None, Some(Parser::binary), Precedence::BitXor, Left)
+            }
+            TokenType::Ampersand => {
+                ParseRule::new(None, Some(Parser::binary), Precedence::BitAnd, Left)
+            }
+            TokenType::LessLess | TokenType::GreaterGreater => {
+                ParseRule::new(None, Some(Parser::binary), Precedence::Shift, Left)
+            }
+            TokenType::BangEqual | TokenType::EqualEqual => {
+                ParseRule::new(None, Some(Parser::binary), Precedence::Equality, Left)
+            }
+            TokenType::Greater
+            | TokenType::GreaterEqual
+            | TokenType::Less
+            | TokenType::LessEqual => {
+                ParseRule::new(None, Some(Parser::binary), Precedence::Comparison, Left)
+            }
+            TokenType::Identifier => {
+                ParseRule::new(Some(Parser::variable), None, Precedence::None, Left)
+            }
+            TokenType::True
+            | TokenType::False
+            | TokenType::Nil
+            | TokenType::Number
+            | TokenType::String => {
+                ParseRule::new(Some(Parser::literal), None, Precedence::None, Left)
+            }
+            _ => ParseRule::new(None, None, Precedence::None, Left),
+        }
+    }
+}
+
+/// Whether `source` stops part way through a statement, string or
+/// comment, so that a REPL should read another line before running it.
+pub fn is_incomplete(source: &str) -> bool {
+    quietly(|| {
+        let mut scanner = Scanner::new(source.to_string());
+        match scanner.scan_tokens() {
+            Ok(tokens) => match Parser::repl(tokens).parse() {
+                Ok(_) => false,
+                Err(e) => e.is_at_end(),
+            },
+            Err(e) => e.is_at_end(),
+        }
+    })
+}
+
+/// Whether two infix operators bind equally tightly, e.g. `+` and `-`.
+pub fn same_precedence(a: TokenType, b: TokenType) -> bool {
+    ParseRule::<RcBuilder>::of(a).precedence == ParseRule::<RcBuilder>::of(b).precedence
+}
+
+/// Lets the parser look back at an expression it has already built, to check
+/// that the left side of `=` or `++` names a variable.
+pub trait AssignTargets: AstBuilder {
+    fn variable_name(&self, expr: &Self::Expr) -> Option<Token>;
+}
+
+impl AssignTargets for RcBuilder {
+    fn variable_name(&self, expr: &Rc<Expr>) -> Option<Token> {
+        match &**expr {
+            Expr::Variable(v) => Some(v.name.mirror()),
+            _ => None,
+        }
+    }
+}
+
+impl AssignTargets for Ast {
+    fn variable_name(&self, expr: &ExprId) -> Option<Token> {
+        match self.expr(*expr) {
+            ExprNode::Variable { name } => Some(name.mirror()),
+            _ => None,
+        }
+    }
+}
+
+/// Parses tokens into syntax trees made by `B`: `Rc` trees for the
+/// interpreter by default, or nodes in an `Ast` arena.
+pub struct Parser<'a, B = RcBuilder> {
     tokens: &'a Vec<Token>,
     current: usize,
+    builder: B,
+    /// First and last token index of each statement parsed, in source
+    /// order, so a test block comes just before the statements in it.
+    spans: Vec<(usize, usize)>,
+    /// Let the final expression statement leave off its ';', as typed at
+    /// the REPL.
+    repl: bool,
 }
 
 impl<'a> Parser<'a> {
-    pub fn new(tokens: &Vec<Token>) -> Parser {
-        Parser { tokens, current: 0 }
+    pub fn new(tokens: &'a Vec<Token>) -> Parser<'a> {
+        Parser::with_builder(tokens, RcBuilder)
     }
 
-    pub fn parse(&mut self) -> Result<Vec<Rc<Stmt>>, JialoxError> {
+    /// A parser for REPL input, where a trailing expression needs no ';'.
+    pub fn repl(tokens: &'a Vec<Token>) -> Parser<'a> {
+        Parser {
+            repl: true,
+            ..Parser::new(tokens)
+        }
+    }
+}
+
+impl<'a, B: AssignTargets> Parser<'a, B> {
+    pub fn with_builder(tokens: &'a Vec<Token>, builder: B) -> Parser<'a, B> {
+        Parser {
+            tokens,
+            current: 0,
+            builder,
+            spans: Vec::new(),
+            repl: false,
+        }
+    }
+
+    /// Hands back the builder, e.g. the `Ast` arena the nodes were added to.
+    pub fn into_builder(self) -> B {
+        self.builder
+    }
+
+    /// The tokens each parsed statement was read from, for tools that need
+    /// to line source positions up with the trees. Statements inside test
+    /// blocks are included, right after the block itself.
+    pub fn statement_spans(&self) -> &[(usize, usize)] {
+        &self.spans
+    }
+
+    pub fn parse(&mut self) -> Result<Vec<B::Stmt>, JialoxError> {
         let mut statements = Vec::new();
+        let mut had_error = None;
         while !self.is_at_end() {
-            statements.push(self.statement()?)
+            match self.spanned_declaration() {
+                Ok(stmt) => statements.push(stmt),
+                Err(e) => {
+                    // Already reported; keep the first and skip to the next
+                    // statement boundary.
+                    had_error.get_or_insert(e);
+                    self.synchronize();
+                }
+            }
+        }
+
+        if let Some(e) = had_error {
+            Err(e)
+        } else {
+            Ok(statements)
         }
-        Ok(statements)
     }
 
-    fn statement(&mut self) -> Result<Rc<Stmt>, JialoxError> {
+    /// A declaration, noting its span ahead of those of the statements
+    /// inside it.
+    fn spanned_declaration(&mut self) -> Result<B::Stmt, JialoxError> {
+        let start = self.current;
+        let index = self.spans.len();
+        let stmt = self.declaration()?;
+        self.spans.insert(index, (start, self.current - 1));
+        Ok(stmt)
+    }
+
+    fn declaration(&mut self) -> Result<B::Stmt, JialoxError> {
+        if self.is_match(&[TokenType::Var]) {
+            return self.var_declaration();
+        }
+        if self.is_match(&[TokenType::Test]) {
+            return self.test_declaration();
+        }
+        self.statement()
+    }
+
+    fn var_declaration(&mut self) -> Result<B::Stmt, JialoxError> {
+        let doc = self.previous().doc();
+        let name = self.consume(TokenType::Identifier, "Expected variable name.")?;
+        // The initializer stops short of the comma operator, so that
+        // `var a = 1, b = 2;` is an error rather than a comma expression.
+        let initializer = if self.is_match(&[TokenType::Equal]) {
+            Some(self.parse_precedence(Precedence::Assignment)?)
+        } else {
+            None
+        };
+        if self.check(TokenType::Comma) {
+            return Err(Self::error(
+                self.currentt(),
+                "Only one variable can be declared per 'var'.",
+            ));
+        }
+        self.consume(
+            TokenType::Semicolon,
+            "Expected ';' after variable declaration.",
+        )?;
+        Ok(self.builder.var_stmt(name, initializer, doc))
+    }
+
+    /// `test "name" { ... }`. The body runs only under `jialox test`, so
+    /// test blocks stay at the top level.
+    fn test_declaration(&mut self) -> Result<B::Stmt, JialoxError> {
+        let name = self.consume(TokenType::String, "Expected test name.")?;
+        self.consume(TokenType::LeftBrace, "Expected '{' before test body.")?;
+        let mut body = Vec::new();
+        while !self.check(TokenType::RightBrace) && !self.is_at_end() {
+            if self.check(TokenType::Test) {
+                return Err(Self::error(
+                    self.currentt(),
+                    "Test blocks cannot be nested.",
+                ));
+            }
+            body.push(self.spanned_declaration()?);
+        }
+        self.consume(TokenType::RightBrace, "Expected '}' after test body.")?;
+        Ok(self.builder.test_stmt(name, body))
+    }
+
+    fn statement(&mut self) -> Result<B::Stmt, JialoxError> {
         if self.is_match(&[TokenType::Print]) {
-            return Ok(Rc::new(self.print_statement()?));
+            return self.print_statement();
         }
-        Ok(Rc::new(self.expression_statement()?))
+        if self.is_match(&[TokenType::Assert]) {
+            return self.assert_statement();
+        }
+        self.expression_statement()
+    }
+
+    fn assert_statement(&mut self) -> Result<B::Stmt, JialoxError> {
+        let keyword = self.previous().mirror();
+        let condition = self.expression()?;
+        self.consume(TokenType::Semicolon, "Expected ';' after assertion.")?;
+        Ok(self.builder.assert_stmt(keyword, condition))
     }
 
-    fn print_statement(&mut self) -> Result<Stmt, JialoxError> {
+    fn print_statement(&mut self) -> Result<B::Stmt, JialoxError> {
         let value = self.expression()?;
         self.consume(TokenType::Semicolon, "Expected ';' after value.")?;
-        Ok(Stmt::Print(Rc::new(PrintStmt {
-            expression: Rc::new(value),
-        })))
+        Ok(self.builder.print_stmt(value))
     }
 
-    fn expression_statement(&mut self) -> Result<Stmt, JialoxError> {
+    fn expression_statement(&mut self) -> Result<B::Stmt, JialoxError> {
         let value = self.expression()?;
+        if self.repl && self.is_at_end() {
+            return Ok(self.builder.expression_stmt(value));
+        }
         self.consume(TokenType::Semicolon, "Expected ';' after value.")?;
-        Ok(Stmt::Expression(Rc::new(ExpressionStmt {
-            expression: Rc::new(value),
-        })))
+        Ok(self.builder.expression_stmt(value))
     }
 
-    fn expression(&mut self) -> Result<Expr, JialoxError> {
-        self.equality()
+    fn expression(&mut self) -> Result<B::Expr, JialoxError> {
+        self.parse_precedence(Precedence::Comma)
     }
 
-    fn equality(&mut self) -> Result<Expr, JialoxError> {
-        let mut expr = self.comparison()?;
+    /// Parses any expression whose operators bind at least as tightly as
+    /// `precedence`, driven by the prefix/infix entries of `ParseRule::of`.
+    fn parse_precedence(&mut self, precedence: Precedence) -> Result<B::Expr, JialoxError> {
+        let rule = ParseRule::<B>::of(self.currentt().ttype());
+        let prefix = match rule.prefix {
+            Some(prefix) => prefix,
+            // '?' needs its ':' half, so it is not worth recovering from here.
+            None if rule.infix.is_some() && !self.check(TokenType::Question) => {
+                return Err(self.missing_left_operand());
+            }
+            None => return Err(Self::error(self.currentt(), "Expected expression.")),
+        };
+        self.advance();
+        let mut expr = prefix(self)?;
 
-        while self.is_match(&[TokenType::BangEqual, TokenType::EqualEqual]) {
-            let operator = self.previous().mirror();
-            let right = self.comparison()?;
-            expr = Expr::Binary(Rc::new(BinaryExpr {
-                left: Rc::new(expr),
-                operator,
-                right: Rc::new(right),
-            }));
+        loop {
+            let rule = ParseRule::<B>::of(self.currentt().ttype());
+            match rule.infix {
+                Some(infix) if precedence <= rule.precedence => {
+                    self.advance();
+                    expr = infix(self, expr)?;
+                }
+                _ => break,
+            }
         }
         Ok(expr)
     }
 
-    fn comparison(&mut self) -> Result<Expr, JialoxError> {
-        let mut comp = self.term()?;
+    fn binary(&mut self, left: B::Expr) -> Result<B::Expr, JialoxError> {
+        let operator = self.previous().mirror();
+        let rule = ParseRule::<B>::of(operator.ttype());
+        let right = match rule.associativity {
+            Associativity::Left => self.parse_precedence(rule.precedence.next())?,
+            Associativity::Right => self.parse_precedence(rule.precedence)?,
+        };
+        Ok(self.builder.binary_expr(left, operator, right))
+    }
+
+    /// Error production for a binary operator at the start of an expression,
+    /// such as `* 3`. The right operand is still parsed at the operator's
+    /// precedence and thrown away, so parsing resumes after it.
+    fn missing_left_operand(&mut self) -> JialoxError {
+        let operator = self.advance().mirror();
+        let err = Self::error(
+            &operator,
+            &format!(
+                "Binary operator '{}' requires a left-hand operand.",
+                operator.lexeme()
+            ),
+        );
+        let rule = ParseRule::<B>::of(operator.ttype());
+        let _ = match rule.associativity {
+            Associativity::Left => self.parse_precedence(rule.precedence.next()),
+            Associativity::Right => self.parse_precedence(rule.precedence),
+        };
+        err
+    }
+
+    /// `=` and the compound assignments. The left operand has already been
+    /// parsed as an ordinary expression, so check it names something
+    /// assignable before building the node.
+    fn assignment(&mut self, target: B::Expr) -> Result<B::Expr, JialoxError> {
+        let operator = self.previous().mirror();
+        let value = self.parse_precedence(Precedence::Assignment)?;
 
-        while self.is_match(&[
-            TokenType::Greater,
-            TokenType::GreaterEqual,
-            TokenType::Less,
-            TokenType::LessEqual,
-        ]) {
-            let operator = self.previous().mirror();
-            let right = self.term()?;
-            comp = Expr::Binary(Rc::new(BinaryExpr {
-                left: Rc::new(comp),
-                operator,
-                right: Rc::new(right),
-            }));
+        let name = match self.builder.variable_name(&target) {
+            Some(name) => name,
+            None => return Err(Self::error(&operator, "Invalid assignment target.")),
+        };
+        if operator.is(TokenType::Equal) {
+            Ok(self.builder.assign_expr(name, value))
+        } else {
+            Ok(self.builder.compound_assign_expr(name, operator, value))
         }
-        Ok(comp)
     }
 
-    fn term(&mut self) -> Result<Expr, JialoxError> {
-        let mut te = self.factor()?;
+    fn prefix_update(&mut self) -> Result<B::Expr, JialoxError> {
+        let operator = self.previous().mirror();
+        let target = self.parse_precedence(Precedence::Postfix)?;
+        self.update(target, operator, true)
+    }
 
-        while self.is_match(&[TokenType::Minus, TokenType::Plus]) {
-            let operator = self.previous().mirror();
-            let right = self.factor()?;
-            te = Expr::Binary(Rc::new(BinaryExpr {
-                left: Rc::new(te),
-                operator,
-                right: Rc::new(right),
-            }));
+    fn postfix_update(&mut self, target: B::Expr) -> Result<B::Expr, JialoxError> {
+        let operator = self.previous().mirror();
+        self.update(target, operator, false)
+    }
+
+    fn update(
+        &mut self,
+        target: B::Expr,
+        operator: Token,
+        prefix: bool,
+    ) -> Result<B::Expr, JialoxError> {
+        match self.builder.variable_name(&target) {
+            Some(name) => Ok(self.builder.update_expr(name, operator, prefix)),
+            None => Err(Self::error(
+                &operator,
+                &format!("Invalid '{}' target.", operator.lexeme()),
+            )),
         }
-        Ok(te)
     }
 
-    fn factor(&mut self) -> Result<Expr, JialoxError> {
-        let mut fac = self.unary()?;
+    /// `condition ? then_branch : else_branch`. Like C, the middle operand may
+    /// be any expression, and the else branch nests to the right.
+    fn conditional(&mut self, condition: B::Expr) -> Result<B::Expr, JialoxError> {
+        let then_branch = self.expression()?;
+        self.consume(
+            TokenType::Colon,
+            "Expected ':' after then branch of conditional expression.",
+        )?;
+        let else_branch = self.parse_precedence(Precedence::Conditional)?;
+        Ok(self
+            .builder
+            .conditional_expr(condition, then_branch, else_branch))
+    }
 
-        while self.is_match(&[TokenType::Slash, TokenType::Star]) {
-            let operator = self.previous().mirror();
-            let right = self.unary()?;
-            fac = Expr::Binary(Rc::new(BinaryExpr {
-                left: Rc::new(fac),
-                operator,
-                right: Rc::new(right),
-            }));
-        }
-        Ok(fac)
+    fn unary(&mut self) -> Result<B::Expr, JialoxError> {
+        let operator = self.previous().mirror();
+        let right = self.parse_precedence(Precedence::Unary)?;
+        Ok(self.builder.unary_expr(operator, right))
     }
 
-    fn unary(&mut self) -> Result<Expr, JialoxError> {
-        if self.is_match(&[TokenType::Bang, TokenType::Minus]) {
-            let operator = self.previous().mirror();
-            let right = self.unary()?;
-            return Ok(Expr::Unary(Rc::new(UnaryExpr {
-                operator,
-                right: Rc::new(right),
-            })));
-        }
-        self.primary()
+    fn literal(&mut self) -> Result<B::Expr, JialoxError> {
+        let token = self.previous().mirror();
+        let value = token.literal();
+        Ok(self.builder.literal_expr(token, value))
     }
 
-    fn primary(&mut self) -> Result<Expr, JialoxError> {
-        if self.is_match(&[
-            TokenType::True,
-            TokenType::False,
-            TokenType::Nil,
-            TokenType::Number,
-            TokenType::String,
-        ]) {
-            return Ok(Expr::Literal(Rc::new(LiteralExpr {
-                value: self.previous().literal(),
-            })));
-        }
-        if self.is_match(&[TokenType::LeftParen]) {
-            let expr = self.expression()?;
-            self.consume(TokenType::RightParen, "Expected ')' after expression.")?;
-            return Ok(Expr::Grouping(Rc::new(GroupingExpr {
-                expression: Rc::new(expr),
-            })));
+    fn variable(&mut self) -> Result<B::Expr, JialoxError> {
+        let name = self.previous().mirror();
+        Ok(self.builder.variable_expr(name))
+    }
+
+    /// `callee(arguments)`. Each argument is parsed above the comma
+    /// operator so the commas separate arguments.
+    fn call(&mut self, callee: B::Expr) -> Result<B::Expr, JialoxError> {
+        let mut arguments = Vec::new();
+        if !self.check(TokenType::RightParen) {
+            loop {
+                arguments.push(self.parse_precedence(Precedence::Assignment)?);
+                if !self.is_match(&[TokenType::Comma]) {
+                    break;
+                }
+            }
         }
-        Err(JialoxError::error(0, "Expected expression"))
+        let paren = self.consume(TokenType::RightParen, "Expected ')' after arguments.")?;
+        Ok(self.builder.call_expr(callee, paren, arguments))
+    }
+
+    fn grouping(&mut self) -> Result<B::Expr, JialoxError> {
+        let expr = self.expression()?;
+        self.consume(TokenType::RightParen, "Expected ')' after expression.")?;
+        Ok(self.builder.grouping_expr(expr))
     }
 
     fn consume(&mut self, ttype: TokenType, message: &str) -> Result<Token, JialoxError> {
         if self.check(ttype) {
             Ok(self.advance().mirror())
         } else {
-            Err(Parser::error(self.currentt(), message))
+            Err(Self::error(self.currentt(), message))
         }
     }
 
@@ -179,6 +569,8 @@ impl<'a> Parser<'a> {
                     | TokenType::While
                     | TokenType::Print
                     | TokenType::Return
+                    | TokenType::Assert
+                    | TokenType::Test
             ) {
                 return;
             }
@@ -222,3 +614,371 @@ impl<'a> Parser<'a> {
         self.tokens.get(self.current - 1).unwrap()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+    use crate::literal::*;
+    use crate::structural_eq::*;
+
+    fn parenthesize(expr: &Expr) -> String {
+        match expr {
+            Expr::Binary(e) => format!(
+                "({} {} {})",
+                e.operator.lexeme(),
+                parenthesize(&e.left),
+                parenthesize(&e.right)
+            ),
+            Expr::Call(e) => {
+                let mut parts = vec![parenthesize(&e.callee)];
+                parts.extend(e.arguments.iter().map(|a| parenthesize(a)));
+                format!("(call {})", parts.join(" "))
+            }
+            Expr::Conditional(e) => format!(
+                "(?: {} {} {})",
+                parenthesize(&e.condition),
+                parenthesize(&e.then_branch),
+                parenthesize(&e.else_branch)
+            ),
+            Expr::Grouping(e) => format!("(group {})", parenthesize(&e.expression)),
+            Expr::Literal(e) => match &e.value {
+                Some(value) => value.to_string(),
+                None => "nil".to_string(),
+            },
+            Expr::Unary(e) => format!("({} {})", e.operator.lexeme(), parenthesize(&e.right)),
+            Expr::Assign(e) => format!("(= {} {})", e.name.lexeme(), parenthesize(&e.value)),
+            Expr::CompoundAssign(e) => format!(
+                "({} {} {})",
+                e.operator.lexeme(),
+                e.name.lexeme(),
+                parenthesize(&e.value)
+            ),
+            Expr::Update(e) if e.prefix => format!("({} {})", e.operator.lexeme(), e.name.lexeme()),
+            Expr::Update(e) => format!("({} {} post)", e.operator.lexeme(), e.name.lexeme()),
+            Expr::Variable(e) => e.name.lexeme(),
+        }
+    }
+
+    fn parse_tree(source: &str) -> Rc<Expr> {
+        let mut scanner = Scanner::new(source.to_string());
+        let tokens = scanner.scan_tokens().unwrap();
+        let mut parser = Parser::new(tokens);
+        let expr = parser.expression().unwrap();
+        assert!(parser.is_at_end());
+        expr
+    }
+
+    fn parse_expression(source: &str) -> String {
+        parenthesize(&parse_tree(source))
+    }
+
+    fn number(n: f64) -> Rc<Expr> {
+        let token = Token::new(TokenType::Number, n.to_string(), Some(Literal::Num(n)), 1);
+        Rc::new(Expr::literal(token, Some(Literal::Num(n))))
+    }
+
+    fn operator(ttype: TokenType, lexeme: &str) -> Token {
+        Token::new(ttype, lexeme.to_string(), None, 1)
+    }
+
+    #[test]
+    fn test_factor_binds_tighter_than_term() {
+        assert_eq!(parse_expression("1 + 2 * 3"), "(+ 1 (* 2 3))");
+        assert_eq!(parse_expression("1 * 2 - 3 / 4"), "(- (* 1 2) (/ 3 4))");
+    }
+
+    #[test]
+    fn test_term_binds_tighter_than_comparison() {
+        assert_eq!(parse_expression("1 + 2 < 3 - 4"), "(< (+ 1 2) (- 3 4))");
+    }
+
+    #[test]
+    fn test_comparison_binds_tighter_than_equality() {
+        assert_eq!(parse_expression("1 < 2 == 3 >= 4"), "(== (< 1 2) (>= 3 4))");
+        assert_eq!(parse_expression("1 != 2 <= 3"), "(!= 1 (<= 2 3))");
+    }
+
+    #[test]
+    fn test_binary_operators_are_left_associative() {
+        assert_eq!(parse_expression("1 - 2 - 3"), "(- (- 1 2) 3)");
+        assert_eq!(parse_expression("8 / 4 / 2"), "(/ (/ 8 4) 2)");
+        assert_eq!(parse_expression("1 == 2 != 3"), "(!= (== 1 2) 3)");
+        assert_eq!(parse_expression("1 < 2 < 3"), "(< (< 1 2) 3)");
+    }
+
+    #[test]
+    fn test_unary_binds_tighter_than_factor() {
+        assert_eq!(parse_expression("-1 * -2"), "(* (- 1) (- 2))");
+        assert_eq!(parse_expression("!!true == false"), "(== (! (! true)) false)");
+    }
+
+    #[test]
+    fn test_modulo_binds_like_factor() {
+        assert_eq!(parse_expression("1 + 7 % 3 * 2"), "(+ 1 (* (% 7 3) 2))");
+    }
+
+    #[test]
+    fn test_power_is_right_associative() {
+        assert_eq!(parse_expression("2 ** 3 ** 2"), "(** 2 (** 3 2))");
+        assert_eq!(parse_expression("2 * 3 ** 2"), "(* 2 (** 3 2))");
+    }
+
+    #[test]
+    fn test_power_binds_tighter_than_unary() {
+        assert_eq!(parse_expression("-2 ** 2"), "(- (** 2 2))");
+        assert_eq!(parse_expression("2 ** -1"), "(** 2 (- 1))");
+    }
+
+    #[test]
+    fn test_bitwise_precedence() {
+        assert_eq!(parse_expression("1 | 2 ^ 3 & 4"), "(| 1 (^ 2 (& 3 4)))");
+        assert_eq!(parse_expression("1 & 2 << 3 + 4"), "(& 1 (<< 2 (+ 3 4)))");
+        assert_eq!(parse_expression("1 | 2 == 3"), "(== (| 1 2) 3)");
+        assert_eq!(parse_expression("~1 >> 2"), "(>> (~ 1) 2)");
+    }
+
+    #[test]
+    fn test_conditional_is_right_associative() {
+        assert_eq!(parse_expression("1 ? 2 : 3 ? 4 : 5"), "(?: 1 2 (?: 3 4 5))");
+        assert_eq!(parse_expression("1 ? 2 ? 3 : 4 : 5"), "(?: 1 (?: 2 3 4) 5)");
+    }
+
+    #[test]
+    fn test_conditional_binds_looser_than_equality() {
+        assert_eq!(
+            parse_expression("1 == 2 ? 3 + 4 : 5"),
+            "(?: (== 1 2) (+ 3 4) 5)"
+        );
+    }
+
+    #[test]
+    fn test_comma_is_lowest_and_left_associative() {
+        assert_eq!(parse_expression("1, 2, 3"), "(, (, 1 2) 3)");
+        assert_eq!(parse_expression("1 ? 2 : 3, 4"), "(, (?: 1 2 3) 4)");
+        assert_eq!(parse_expression("1 ? 2, 3 : 4"), "(?: 1 (, 2 3) 4)");
+    }
+
+    #[test]
+    fn test_conditional_requires_colon() {
+        let mut scanner = Scanner::new("1 ? 2".to_string());
+        let tokens = scanner.scan_tokens().unwrap();
+        let mut parser = Parser::new(tokens);
+        assert!(parser.expression().is_err());
+    }
+
+    #[test]
+    fn test_assignment_is_right_associative() {
+        assert_eq!(parse_expression("a = b = 1 + 2"), "(= a (= b (+ 1 2)))");
+        assert_eq!(parse_expression("a += b -= 2"), "(+= a (-= b 2))");
+    }
+
+    #[test]
+    fn test_assignment_binds_looser_than_conditional_and_tighter_than_comma() {
+        assert_eq!(parse_expression("a *= b ? 1 : 2"), "(*= a (?: b 1 2))");
+        assert_eq!(parse_expression("a = 1, b %= 2"), "(, (= a 1) (%= b 2))");
+    }
+
+    #[test]
+    fn test_invalid_assignment_target() {
+        assert_eq!(parse_expression_error("a + b = 3;"), TokenType::Semicolon);
+        assert_eq!(parse_expression_error("(a) /= 3;"), TokenType::Semicolon);
+    }
+
+    #[test]
+    fn test_increment_and_decrement() {
+        assert_eq!(parse_expression("++a * b--"), "(* (++ a) (-- b post))");
+        assert_eq!(parse_expression("-a++"), "(- (++ a post))");
+        assert_eq!(parse_expression("a+++b"), "(+ (++ a post) b)");
+        assert_eq!(parse_expression_error("++1;"), TokenType::Semicolon);
+        assert_eq!(parse_expression_error("a++++;"), TokenType::Semicolon);
+    }
+
+    #[test]
+    fn test_grouping_overrides_precedence() {
+        assert_eq!(parse_expression("(1 + 2) * 3"), "(* (group (+ 1 2)) 3)");
+        assert_eq!(parse_expression("-(1 - 2)"), "(- (group (- 1 2)))");
+    }
+
+    #[test]
+    fn test_primary_literals() {
+        assert_eq!(parse_expression("\"hi\""), "hi");
+        assert_eq!(parse_expression("nil"), "nil");
+        assert_eq!(parse_expression("4.5"), "4.5");
+    }
+
+    #[test]
+    fn test_call_arguments_are_split_on_commas() {
+        assert_eq!(parse_expression("f()"), "(call f)");
+        assert_eq!(parse_expression("f(1, a = 2)"), "(call f 1 (= a 2))");
+        assert_eq!(parse_expression("f((1, 2))"), "(call f (group (, 1 2)))");
+        assert_eq!(parse_expression("-f(1)(2)"), "(- (call (call f 1) 2))");
+        assert_eq!(parse_expression("f(1) ** 2"), "(** (call f 1) 2)");
+    }
+
+    fn parse_expression_error(source: &str) -> TokenType {
+        let mut scanner = Scanner::new(source.to_string());
+        let tokens = scanner.scan_tokens().unwrap();
+        let mut parser = Parser::new(tokens);
+        assert!(parser.expression().is_err());
+        parser.currentt().ttype()
+    }
+
+    #[test]
+    fn test_missing_left_operand_discards_right_operand() {
+        assert_eq!(parse_expression_error("* 3;"), TokenType::Semicolon);
+        assert_eq!(
+            parse_expression_error("== 1 + 2 < 3;"),
+            TokenType::Semicolon
+        );
+        assert_eq!(parse_expression_error("** 2 ** 3)"), TokenType::RightParen);
+    }
+
+    #[test]
+    fn test_missing_left_operand_respects_precedence() {
+        assert_eq!(
+            parse_expression_error("+ 1 * 2 == 3"),
+            TokenType::EqualEqual
+        );
+        assert_eq!(parse_expression_error("<< 1 + 2 & 3"), TokenType::Ampersand);
+    }
+
+    #[test]
+    fn test_parse_continues_after_missing_left_operand() {
+        let mut scanner = Scanner::new("/ 2; print 1; >= 4;".to_string());
+        let tokens = scanner.scan_tokens().unwrap();
+        let mut parser = Parser::with_builder(tokens, Ast::new());
+        let (result, errors) = capture_errors(|| parser.parse());
+        assert!(result.is_err());
+        assert!(parser.is_at_end());
+        // One error for each bad expression, none for the operands skipped.
+        assert_eq!(
+            errors,
+            [
+                "[line 1] Error at '/': Binary operator '/' requires a left-hand operand.",
+                "[line 1] Error at '>=': Binary operator '>=' requires a left-hand operand.",
+            ]
+        );
+
+        // `print 1;` in between was parsed, and nothing else was.
+        assert_eq!(parser.statement_spans(), [(3, 5)]);
+        let lexemes: Vec<String> = tokens[3..=5].iter().map(Token::lexeme).collect();
+        assert_eq!(lexemes, ["print", "1", ";"]);
+        assert_eq!(parser.into_builder().stmt_count(), 1);
+    }
+
+    #[test]
+    fn test_var_declares_one_variable() {
+        let mut scanner = Scanner::new("var a = 1, b = 2; var c = (1, 2);".to_string());
+        let tokens = scanner.scan_tokens().unwrap();
+        let mut parser = Parser::new(tokens);
+        let (result, errors) = capture_errors(|| parser.parse());
+        assert!(result.is_err());
+        assert_eq!(
+            errors,
+            ["[line 1] Error at ',': Only one variable can be declared per 'var'."]
+        );
+        // A parenthesised comma expression is still a single initializer.
+        assert_eq!(parser.statement_spans().len(), 1);
+    }
+
+    #[test]
+    fn test_parse_matches_hand_built_tree() {
+        let expected = Rc::new(Expr::binary(
+            number(1.0),
+            operator(TokenType::Plus, "+"),
+            Rc::new(Expr::binary(
+                number(2.0),
+                operator(TokenType::Star, "*"),
+                number(3.0),
+            )),
+        ));
+        assert!(parse_tree("1 + 2 * 3").structural_eq(&expected));
+        assert!(!parse_tree("(1 + 2) * 3").structural_eq(&expected));
+        assert!(!parse_tree("1 - 2 * 3").structural_eq(&expected));
+    }
+
+    #[test]
+    fn test_display_prints_tree() {
+        assert_eq!(
+            parse_tree("-a + 2 * 3").to_string(),
+            "(Binary (Unary - (Variable a)) + (Binary (Literal 2 2) * (Literal 3 3)))"
+        );
+    }
+
+    #[test]
+    fn test_separate_parses_are_distinct_keys() {
+        let first = parse_tree("1 + 2");
+        let second = parse_tree("1 + 2");
+        assert!(first.structural_eq(&second));
+        assert!(first != second);
+
+        let mut seen = HashSet::new();
+        assert!(seen.insert(&first));
+        assert!(seen.insert(&second));
+        assert!(!seen.insert(&first));
+    }
+
+    #[test]
+    fn test_clones_share_their_children() {
+        let tree = parse_tree("1 + 2");
+        let copy = (*tree).clone();
+        assert!(copy == *tree);
+
+        let Expr::Binary(binary) = &*tree else {
+            panic!("not a binary expression");
+        };
+        let node = (**binary).clone();
+        assert!(Rc::ptr_eq(&node.left, &binary.left));
+        assert!(node.operator.structural_eq(&binary.operator));
+    }
+
+    #[test]
+    fn test_missing_operand_is_an_error() {
+        let mut scanner = Scanner::new("1 +".to_string());
+        let tokens = scanner.scan_tokens().unwrap();
+        let mut parser = Parser::new(tokens);
+        assert!(parser.expression().is_err());
+    }
+
+    #[test]
+    fn test_repl_mode_allows_trailing_expression() {
+        let source = "var a = 1; a + 2";
+        let mut scanner = Scanner::new(source.to_string());
+        let tokens = scanner.scan_tokens().unwrap();
+        assert_eq!(Parser::repl(tokens).parse().unwrap().len(), 2);
+        assert!(Parser::new(tokens).parse().is_err());
+
+        let mut scanner = Scanner::new("1 2".to_string());
+        let tokens = scanner.scan_tokens().unwrap();
+        assert!(Parser::repl(tokens).parse().is_err());
+    }
+
+    #[test]
+    fn test_incomplete_input() {
+        for source in [
+            "print 1",
+            "var a =",
+            "print (1 +",
+            "print 1 ?",
+            "print \"unterminated",
+            "/* open comment",
+            "var a = 1;\nprint a *",
+        ] {
+            assert!(is_incomplete(source), "{source}");
+        }
+        for source in [
+            "",
+            "1 + 2",
+            "print 1;",
+            "var a = 1; // comment",
+            "print 1 +;",
+            "print );",
+            "print 1 @ 2",
+            "1 = 2; print (",
+        ] {
+            assert!(!is_incomplete(source), "{source}");
+        }
+    }
+}
@@ -8,6 +8,15 @@ use crate::token_type::*;
 pub struct Scanner {
     source: Vec<char>,
     tokens: Vec<Token>,
+    /// Doc comment lines waiting for the next token.
+    docs: Vec<String>,
+    /// Keep whitespace and comments on the tokens (see `Scanner::lossless`).
+    lossless: bool,
+    /// Trivia waiting for the next token, in lossless mode.
+    pending: Vec<Trivia>,
+    /// Whether the current line already has a token, whose trailing trivia
+    /// takes anything up to the newline.
+    on_token_line: bool,
     start: usize,
     current: usize,
     line: usize,
@@ -21,6 +30,10 @@ impl Scanner {
         Scanner {
             source: source.chars().collect(),
             tokens: Vec::new(),
+            docs: Vec::new(),
+            lossless: false,
+            pending: Vec::new(),
+            on_token_line: false,
             start: 0,
             current: 0,
             line: 1,
@@ -28,8 +41,27 @@ impl Scanner {
         }
     }
 
+    /// A scanner whose tokens carry their surrounding whitespace and comments
+    /// as trivia, so that tools such as the formatter can see the input
+    /// exactly.
+    pub fn lossless(source: String) -> Scanner {
+        let mut scanner = Scanner::new(source);
+        scanner.lossless = true;
+        scanner
+    }
+
+    /// The reserved words, sorted.
+    pub fn keywords() -> Vec<String> {
+        let mut keywords = HashMap::new();
+        Scanner::init_keywords(&mut keywords);
+        let mut names: Vec<String> = keywords.into_keys().collect();
+        names.sort();
+        names
+    }
+
     fn init_keywords(keywords: &mut HashMap<String, TokenType>) {
         keywords.insert("add".to_string(), TokenType::And);
+        keywords.insert("assert".to_string(), TokenType::Assert);
         keywords.insert("class".to_string(), TokenType::Class);
         keywords.insert("else".to_string(), TokenType::Else);
         keywords.insert("false".to_string(), TokenType::False);
@@ -41,6 +73,7 @@ impl Scanner {
         keywords.insert("print".to_string(), TokenType::Print);
         keywords.insert("return".to_string(), TokenType::Return);
         keywords.insert("super".to_string(), TokenType::Super);
+        keywords.insert("test".to_string(), TokenType::Test);
         keywords.insert("this".to_string(), TokenType::This);
         keywords.insert("true".to_string(), TokenType::True);
         keywords.insert("var".to_string(), TokenType::Var);
@@ -49,17 +82,30 @@ impl Scanner {
 
     pub fn scan_tokens(&mut self) -> Result<&Vec<Token>, JialoxError> {
         let mut had_error = None;
+        if self.source.starts_with(&['#', '!']) {
+            self.skip_shebang();
+        }
         while !self.is_at_end() {
             self.start = self.current;
+            let count = self.tokens.len();
             match self.scan_token() {
+                Ok(_) if self.lossless => self.attach_trivia(count),
                 Ok(_) => {}
+                // Already reported when it was made.
                 Err(e) => {
-                    e.report("");
-                    had_error = Some(e);
+                    had_error.get_or_insert(e);
                 }
             }
         }
-        self.tokens.push(Token::eof(self.line));
+        let mut eof = Token::eof(self.line);
+        eof.set_span(Span {
+            start: self.current,
+            end: self.current,
+        });
+        if self.lossless {
+            eof.set_leading_trivia(std::mem::take(&mut self.pending));
+        }
+        self.tokens.push(eof);
 
         if let Some(e) = had_error {
             Err(e)
@@ -68,6 +114,55 @@ impl Scanner {
         }
     }
 
+    /// Skips the `#!` line that makes a script executable. It is kept as a
+    /// line comment so lossless mode, and with it the formatter, leaves it
+    /// in place.
+    fn skip_shebang(&mut self) {
+        while matches!(self.currentc(), Some(ch) if ch != '\n') {
+            self.advance();
+        }
+        if self.lossless {
+            self.attach_trivia(0);
+        }
+    }
+
+    /// Files the text just scanned: a new token takes the pending trivia as
+    /// its leading trivia, anything else is trivia itself.
+    fn attach_trivia(&mut self, count: usize) {
+        if self.tokens.len() > count {
+            let leading = std::mem::take(&mut self.pending);
+            self.tokens[count].set_leading_trivia(leading);
+            self.on_token_line = true;
+            return;
+        }
+
+        let text: String = self.source[self.start..self.current].iter().collect();
+        let kind = if text.starts_with("//") || text.starts_with("#!") {
+            TriviaKind::LineComment
+        } else if text.starts_with("/*") {
+            TriviaKind::BlockComment
+        } else if text == "\n" {
+            self.on_token_line = false;
+            TriviaKind::Newline
+        } else {
+            TriviaKind::Whitespace
+        };
+        let trivia = match self.tokens.last_mut() {
+            Some(token) if self.on_token_line => token.trailing_trivia_mut(),
+            _ => &mut self.pending,
+        };
+        match trivia.last_mut() {
+            // Runs of blanks or of newlines make one piece each.
+            Some(last)
+                if last.kind == kind
+                    && matches!(kind, TriviaKind::Whitespace | TriviaKind::Newline) =>
+            {
+                last.text.push_str(&text)
+            }
+            _ => trivia.push(Trivia { kind, text }),
+        }
+    }
+
     fn is_at_end(&self) -> bool {
         self.current >= self.source.len()
     }
@@ -81,10 +176,51 @@ impl Scanner {
             '}' => self.add_terminator(TokenType::RightBrace),
             ',' => self.add_terminator(TokenType::Comma),
             '.' => self.add_terminator(TokenType::Dot),
-            '-' => self.add_terminator(TokenType::Minus),
-            '+' => self.add_terminator(TokenType::Plus),
+            '-' => {
+                let tt = if self.next_match('-') {
+                    TokenType::MinusMinus
+                } else if self.next_match('=') {
+                    TokenType::MinusEqual
+                } else {
+                    TokenType::Minus
+                };
+                self.add_terminator(tt);
+            }
+            '+' => {
+                let tt = if self.next_match('+') {
+                    TokenType::PlusPlus
+                } else if self.next_match('=') {
+                    TokenType::PlusEqual
+                } else {
+                    TokenType::Plus
+                };
+                self.add_terminator(tt);
+            }
             ';' => self.add_terminator(TokenType::Semicolon),
-            '*' => self.add_terminator(TokenType::Star),
+            '?' => self.add_terminator(TokenType::Question),
+            ':' => self.add_terminator(TokenType::Colon),
+            '*' => {
+                let tt = if self.next_match('*') {
+                    TokenType::StarStar
+                } else if self.next_match('=') {
+                    TokenType::StarEqual
+                } else {
+                    TokenType::Star
+                };
+                self.add_terminator(tt);
+            }
+            '%' => {
+                let tt = if self.next_match('=') {
+                    TokenType::PercentEqual
+                } else {
+                    TokenType::Percent
+                };
+                self.add_terminator(tt);
+            }
+            '&' => self.add_terminator(TokenType::Ampersand),
+            '|' => self.add_terminator(TokenType::Pipe),
+            '^' => self.add_terminator(TokenType::Caret),
+            '~' => self.add_terminator(TokenType::Tilde),
             '!' => {
                 let tt = if self.next_match('=') {
                     TokenType::BangEqual
@@ -104,6 +240,8 @@ impl Scanner {
             '<' => {
                 let tt = if self.next_match('=') {
                     TokenType::LessEqual
+                } else if self.next_match('<') {
+                    TokenType::LessLess
                 } else {
                     TokenType::Less
                 };
@@ -112,6 +250,8 @@ impl Scanner {
             '>' => {
                 let tt = if self.next_match('=') {
                     TokenType::GreaterEqual
+                } else if self.next_match('>') {
+                    TokenType::GreaterGreater
                 } else {
                     TokenType::Greater
                 };
@@ -127,9 +267,13 @@ impl Scanner {
                             break;
                         }
                     }
+                    self.add_comment();
                 } else if self.next_match('*') {
                     // block
                     self.scan_block_comments()?;
+                    self.add_comment();
+                } else if self.next_match('=') {
+                    self.add_terminator(TokenType::SlashEqual);
                 } else {
                     self.add_terminator(TokenType::Slash);
                 }
@@ -166,8 +310,52 @@ impl Scanner {
 
     fn add_token(&mut self, ttype: TokenType, literal: Option<Literal>) {
         let lexeme: String = self.source[self.start..self.current].iter().collect();
-        self.tokens
-            .push(Token::new(ttype, lexeme, literal, self.line));
+        let mut token = Token::new(ttype, lexeme, literal, self.line);
+        token.set_span(Span {
+            start: self.start,
+            end: self.current,
+        });
+        if !self.docs.is_empty() {
+            token.set_doc(self.docs.join("\n"));
+            self.docs.clear();
+        }
+        self.tokens.push(token);
+    }
+
+    fn add_comment(&mut self) {
+        let text: String = self.source[self.start..self.current].iter().collect();
+        if let Some(doc) = Scanner::doc_text(&text) {
+            self.docs.push(doc);
+        }
+    }
+
+    /// The text of a `///` or `/** */` doc comment, without its markers.
+    /// `////` and `/**/` are ordinary comments.
+    fn doc_text(comment: &str) -> Option<String> {
+        if let Some(line) = comment.strip_prefix("///") {
+            if line.starts_with('/') {
+                return None;
+            }
+            return Some(
+                line.strip_prefix(' ')
+                    .unwrap_or(line)
+                    .trim_end()
+                    .to_string(),
+            );
+        }
+        let body = comment.strip_prefix("/**")?.strip_suffix("*/")?;
+        if body.is_empty() || body.starts_with('*') {
+            return None;
+        }
+        let lines: Vec<&str> = body
+            .lines()
+            .map(|line| {
+                let line = line.trim();
+                let line = line.strip_prefix('*').unwrap_or(line);
+                line.strip_prefix(' ').unwrap_or(line).trim_end()
+            })
+            .collect();
+        Some(lines.join("\n").trim().to_string())
     }
 
     fn next_match(&mut self, expected: char) -> bool {
@@ -194,7 +382,7 @@ impl Scanner {
             self.advance();
         }
         if self.is_at_end() {
-            return Err(JialoxError::error(self.line, "Unterminated string."));
+            return Err(JialoxError::unterminated(self.line, "Unterminated string."));
         }
         self.advance();
         // TODO: handle escape sequences
@@ -292,7 +480,10 @@ impl Scanner {
                     self.line += 1;
                 }
                 None => {
-                    return Err(JialoxError::error(self.line, "Unterminated block comments"));
+                    return Err(JialoxError::unterminated(
+                        self.line,
+                        "Unterminated block comments",
+                    ));
                 }
                 _ => {
                     self.advance();
@@ -301,3 +492,147 @@ impl Scanner {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The source text the tokens of a lossless scan were read from.
+    fn source_of(tokens: &[Token]) -> String {
+        let mut source = String::new();
+        for token in tokens {
+            for trivia in token.leading_trivia() {
+                source.push_str(&trivia.text);
+            }
+            source.push_str(&token.lexeme());
+            for trivia in token.trailing_trivia() {
+                source.push_str(&trivia.text);
+            }
+        }
+        source
+    }
+
+    fn scan_lossless(source: &str) -> Vec<Token> {
+        let mut scanner = Scanner::lossless(source.to_string());
+        let tokens = scanner.scan_tokens().unwrap();
+        tokens.iter().map(Token::mirror).collect()
+    }
+
+    #[test]
+    fn test_round_trip_example() {
+        let source = std::fs::read_to_string("grammar/example.txt").unwrap();
+        assert_eq!(source_of(&scan_lossless(&source)), source);
+    }
+
+    #[test]
+    fn test_round_trip_edge_cases() {
+        for source in [
+            "",
+            "   \n\n",
+            "var a = 1;",
+            "\tprint \"multi\nline\";\r\n// no newline at end",
+            "a /* one\n two */ b // c\n\n  /* d /* e */ */",
+        ] {
+            assert_eq!(source_of(&scan_lossless(source)), source);
+        }
+    }
+
+    #[test]
+    fn test_trivia_placement() {
+        let tokens = scan_lossless("// lead\n  var a; // trail\nprint a;\n");
+        let var = &tokens[0];
+        assert_eq!(
+            var.leading_trivia(),
+            [
+                Trivia {
+                    kind: TriviaKind::LineComment,
+                    text: "// lead".to_string()
+                },
+                Trivia {
+                    kind: TriviaKind::Newline,
+                    text: "\n".to_string()
+                },
+                Trivia {
+                    kind: TriviaKind::Whitespace,
+                    text: "  ".to_string()
+                },
+            ]
+        );
+        assert_eq!(var.trailing_trivia()[0].text, " ");
+
+        let semicolon = &tokens[2];
+        let trailing: Vec<&str> = semicolon
+            .trailing_trivia()
+            .iter()
+            .map(|t| t.text.as_str())
+            .collect();
+        assert_eq!(trailing, [" ", "// trail"]);
+        assert_eq!(tokens[3].leading_trivia()[0].kind, TriviaKind::Newline);
+        assert_eq!(tokens.last().unwrap().leading_trivia()[0].text, "\n");
+    }
+
+    #[test]
+    fn test_doc_comments() {
+        let mut scanner = Scanner::new(
+            "/// One.\n///\n///   Two.\nvar a;\n//// not a doc\nvar b;\n\
+             /** Three. */ var c;\n/**\n * Four.\n *\n * Five.\n */\nvar d;\n/**/ var e;"
+                .to_string(),
+        );
+        let tokens = scanner.scan_tokens().unwrap();
+        let docs: Vec<Option<String>> = tokens
+            .iter()
+            .filter(|t| t.is(TokenType::Var))
+            .map(Token::doc)
+            .collect();
+        assert_eq!(
+            docs,
+            [
+                Some("One.\n\n  Two.".to_string()),
+                None,
+                Some("Three.".to_string()),
+                Some("Four.\n\nFive.".to_string()),
+                None,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_default_scan_has_no_trivia() {
+        let mut scanner = Scanner::new("var a; // c\n".to_string());
+        let tokens = scanner.scan_tokens().unwrap();
+        assert!(tokens
+            .iter()
+            .all(|t| t.leading_trivia().is_empty() && t.trailing_trivia().is_empty()));
+    }
+
+    #[test]
+    fn test_spans_are_character_offsets() {
+        let source = "print \"caf\u{e9}\" + x;";
+        let mut scanner = Scanner::new(source.to_string());
+        let tokens = scanner.scan_tokens().unwrap();
+        let chars: Vec<char> = source.chars().collect();
+        for token in &tokens[..tokens.len() - 1] {
+            let Span { start, end } = token.span();
+            let text: String = chars[start..end].iter().collect();
+            assert_eq!(text, token.lexeme());
+        }
+        let eof = tokens.last().unwrap().span();
+        assert_eq!((eof.start, eof.end), (chars.len(), chars.len()));
+    }
+
+    #[test]
+    fn test_shebang_line_is_skipped() {
+        let source = "#!/usr/bin/env jialox\nprint 1;\n";
+        let mut scanner = Scanner::new(source.to_string());
+        let tokens = scanner.scan_tokens().unwrap();
+        assert!(tokens[0].is(TokenType::Print));
+        assert_eq!(tokens[0].line(), 2);
+        let tokens = scan_lossless(source);
+        assert_eq!(tokens[0].leading_trivia()[0].kind, TriviaKind::LineComment);
+        assert_eq!(source_of(&tokens), source);
+
+        // Only on the first line.
+        let mut scanner = Scanner::new("print 1;\n#!x".to_string());
+        assert!(quietly(|| scanner.scan_tokens().is_err()));
+    }
+}
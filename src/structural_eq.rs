@@ -0,0 +1,61 @@
+use std::rc::Rc;
+
+use crate::literal::*;
+use crate::token::*;
+
+/// Equality by shape and content rather than identity, so two separately
+/// parsed (or hand-built) trees can be compared. Token line numbers are
+/// ignored.
+// Only the tests compare trees so far.
+#[allow(dead_code)]
+pub trait StructuralEq {
+    fn structural_eq(&self, other: &Self) -> bool;
+}
+
+impl StructuralEq for Token {
+    fn structural_eq(&self, other: &Self) -> bool {
+        self.ttype() == other.ttype()
+            && self.lexeme() == other.lexeme()
+            && self.literal() == other.literal()
+    }
+}
+
+impl StructuralEq for Literal {
+    fn structural_eq(&self, other: &Self) -> bool {
+        self == other
+    }
+}
+
+impl StructuralEq for String {
+    fn structural_eq(&self, other: &Self) -> bool {
+        self == other
+    }
+}
+
+impl StructuralEq for bool {
+    fn structural_eq(&self, other: &Self) -> bool {
+        self == other
+    }
+}
+
+impl<T: StructuralEq> StructuralEq for Rc<T> {
+    fn structural_eq(&self, other: &Self) -> bool {
+        (**self).structural_eq(other)
+    }
+}
+
+impl<T: StructuralEq> StructuralEq for Option<T> {
+    fn structural_eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Some(a), Some(b)) => a.structural_eq(b),
+            (None, None) => true,
+            _ => false,
+        }
+    }
+}
+
+impl<T: StructuralEq> StructuralEq for Vec<T> {
+    fn structural_eq(&self, other: &Self) -> bool {
+        self.len() == other.len() && self.iter().zip(other).all(|(a, b)| a.structural_eq(b))
+    }
+}
@@ -0,0 +1,143 @@
+use std::rc::Rc;
+
+use crate::error::*;
+use crate::interpreter::*;
+use crate::literal::*;
+use crate::stmt::*;
+
+/// How one `test` block went.
+#[derive(Debug)]
+pub struct TestOutcome {
+    pub name: String,
+    /// Everything the block printed.
+    pub output: String,
+    /// The line and message of the error that stopped the block.
+    pub failure: Option<(usize, String)>,
+}
+
+/// The program stopped before its tests could run.
+#[derive(Debug)]
+pub struct SetupFailure {
+    /// The line and message of the error, or of the `exit()` call, that
+    /// stopped it.
+    pub failure: (usize, String),
+    /// Everything the program printed until then.
+    pub output: String,
+}
+
+/// Runs a program once, leaving out its `test` blocks, and then runs each
+/// block on its own in a fresh copy of the globals the program set up. An
+/// error or an `exit()` in the program itself fails the whole file.
+pub fn run_tests(statements: &[Rc<Stmt>]) -> Result<Vec<TestOutcome>, SetupFailure> {
+    let buffer = SharedBuffer::default();
+    let mut setup = Interpreter::with_output(Box::new(buffer.clone()));
+    if let Err(e) = quietly(|| setup.interpret(statements)) {
+        return Err(SetupFailure {
+            failure: (e.line(), e.message().to_string()),
+            output: buffer.contents(),
+        });
+    }
+
+    let mut outcomes = Vec::new();
+    for stmt in statements {
+        let Stmt::Test(test) = &**stmt else {
+            continue;
+        };
+        let buffer = SharedBuffer::default();
+        let mut interpreter = setup.fork(Box::new(buffer.clone()));
+        let result = quietly(|| interpreter.interpret(&test.body));
+        let name = match test.name.literal() {
+            Some(Literal::Str(name)) => name,
+            _ => test.name.lexeme(),
+        };
+        outcomes.push(TestOutcome {
+            name,
+            output: buffer.contents(),
+            failure: result.err().map(|e| (e.line(), e.message().to_string())),
+        });
+    }
+    Ok(outcomes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::*;
+    use crate::scanner::*;
+
+    fn tests(source: &str) -> Result<Vec<TestOutcome>, SetupFailure> {
+        let mut scanner = Scanner::new(source.to_string());
+        let tokens = scanner.scan_tokens().unwrap();
+        let statements = Parser::new(tokens).parse().unwrap();
+        run_tests(&statements)
+    }
+
+    fn run(source: &str) -> Vec<(String, Option<(usize, String)>)> {
+        tests(source)
+            .unwrap()
+            .into_iter()
+            .map(|outcome| (outcome.name, outcome.failure))
+            .collect()
+    }
+
+    fn setup_failure(source: &str) -> ((usize, String), String) {
+        let setup = tests(source).unwrap_err();
+        (setup.failure, setup.output)
+    }
+
+    #[test]
+    fn test_blocks_run_in_isolation() {
+        let outcomes = run("var a = 1;\n\
+             test \"first\" { a += 1; assert a == 2; }\n\
+             test \"second\" { assert a == 1; }\n\
+             test \"fails\" {\n  assert a == 1;\n  assert a > 1;\n}\n\
+             test \"equal\" { assert_eq(a + 1, \"2\", \"sum\"); }");
+        assert_eq!(
+            outcomes,
+            [
+                ("first".to_string(), None),
+                ("second".to_string(), None),
+                (
+                    "fails".to_string(),
+                    Some((6, "Assertion failed.".to_string()))
+                ),
+                (
+                    "equal".to_string(),
+                    Some((8, "sum: 2 != \"2\"".to_string()))
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_output_is_the_blocks_own() {
+        let outcomes = tests("print 1;\ntest \"t\" { print 2; }\nprint 3;").unwrap();
+        assert_eq!(outcomes[0].output, "2\n");
+    }
+
+    #[test]
+    fn test_exit_in_the_program_fails_the_setup() {
+        let source = "print 1;\nexit(0);\ntest \"passes\" { assert true; }";
+        assert_eq!(
+            setup_failure(source),
+            ((2, "exit(0)".to_string()), "1\n".to_string())
+        );
+    }
+
+    #[test]
+    fn test_runtime_error_in_the_program_fails_the_setup() {
+        let source = "var a = 1;\nprint b;\ntest \"one\" {}\ntest \"two\" {}";
+        assert_eq!(
+            setup_failure(source),
+            ((2, "Undefined variable 'b'.".to_string()), String::new())
+        );
+    }
+
+    #[test]
+    fn test_blocks_do_not_run_with_the_program() {
+        assert_eq!(
+            capture_output("print 1; test \"t\" { print 2; } print 3;").unwrap(),
+            "1\n3\n"
+        );
+    }
+}
@@ -3,12 +3,49 @@ use std::fmt;
 use crate::literal::*;
 use crate::token_type::*;
 
-#[derive(Debug)]
+/// Source text between tokens that the grammar ignores.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TriviaKind {
+    Whitespace,
+    Newline,
+    LineComment,
+    BlockComment,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Trivia {
+    pub kind: TriviaKind,
+    pub text: String,
+}
+
+/// Where a token sits in its source, as character offsets of the first
+/// character and one past the last.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+#[derive(Debug, Clone)]
 pub struct Token {
     ttype: TokenType,
     lexeme: String,
     literal: Option<Literal>,
     line: usize,
+    /// Empty for tokens that were not scanned from source.
+    span: Span,
+    /// Boxed to keep tokens, and the errors that carry them, small.
+    trivia: Option<Box<TokenTrivia>>,
+}
+
+#[derive(Debug, Clone, Default)]
+struct TokenTrivia {
+    /// The `///` or `/** */` comment before the token, in any scan mode.
+    doc: Option<String>,
+    /// Trivia before the token, filled in only by a lossless scan.
+    leading: Vec<Trivia>,
+    /// Trivia after the token up to the end of its line.
+    trailing: Vec<Trivia>,
 }
 
 impl Token {
@@ -18,6 +55,8 @@ impl Token {
             lexeme,
             literal,
             line,
+            span: Span::default(),
+            trivia: None,
         }
     }
 
@@ -27,6 +66,8 @@ impl Token {
             lexeme: "".to_string(),
             literal: None,
             line,
+            span: Span::default(),
+            trivia: None,
         }
     }
 
@@ -35,12 +76,7 @@ impl Token {
     }
 
     pub fn mirror(&self) -> Token {
-        Token {
-            ttype: self.ttype,
-            lexeme: self.lexeme.clone(),
-            literal: self.literal.clone(),
-            line: self.line,
-        }
+        self.clone()
     }
 
     pub fn literal(&self) -> Option<Literal> {
@@ -55,6 +91,14 @@ impl Token {
         self.line
     }
 
+    pub fn span(&self) -> Span {
+        self.span
+    }
+
+    pub fn set_span(&mut self, span: Span) {
+        self.span = span;
+    }
+
     pub fn ttype(&self) -> TokenType {
         self.ttype
     }
@@ -62,6 +106,30 @@ impl Token {
     pub fn lexeme(&self) -> String {
         self.lexeme.clone()
     }
+
+    pub fn doc(&self) -> Option<String> {
+        self.trivia.as_ref().and_then(|t| t.doc.clone())
+    }
+
+    pub fn set_doc(&mut self, doc: String) {
+        self.trivia.get_or_insert_with(Default::default).doc = Some(doc);
+    }
+
+    pub fn leading_trivia(&self) -> &[Trivia] {
+        self.trivia.as_ref().map_or(&[], |t| &t.leading)
+    }
+
+    pub fn trailing_trivia(&self) -> &[Trivia] {
+        self.trivia.as_ref().map_or(&[], |t| &t.trailing)
+    }
+
+    pub fn set_leading_trivia(&mut self, leading: Vec<Trivia>) {
+        self.trivia.get_or_insert_with(Default::default).leading = leading;
+    }
+
+    pub fn trailing_trivia_mut(&mut self) -> &mut Vec<Trivia> {
+        &mut self.trivia.get_or_insert_with(Default::default).trailing
+    }
 }
 
 impl fmt::Display for Token {
@@ -10,10 +10,24 @@ pub enum TokenType {
     Minus,
     Plus,
     Semicolon,
+    Question,
+    Colon,
     Slash,
     Star,
+    Percent,
+    Ampersand,
+    Pipe,
+    Caret,
+    Tilde,
 
     // One or two character tokens
+    PlusPlus,     // Increment '++'
+    MinusMinus,   // Decrement '--'
+    PlusEqual,    // '+='
+    MinusEqual,   // '-='
+    StarEqual,    // '*='
+    SlashEqual,   // '/='
+    PercentEqual, // '%='
     Bang,
     BangEqual,
     Equal,      // Assign '='
@@ -22,6 +36,9 @@ pub enum TokenType {
     GreaterEqual,
     Less,
     LessEqual,
+    LessLess,       // Shift left  '<<'
+    GreaterGreater, // Shift right '>>'
+    StarStar,       // Power       '**'
 
     // Literals
     Identifier,
@@ -30,6 +47,7 @@ pub enum TokenType {
 
     // Keywords
     And,
+    Assert,
     Class,
     Else,
     False,
@@ -41,6 +59,7 @@ pub enum TokenType {
     Print,
     Return,
     Super,
+    Test,
     This,
     True,
     Var,
@@ -48,3 +67,67 @@ pub enum TokenType {
 
     Eof,
 }
+
+impl TokenType {
+    /// Every token type, for looking one up by its `Debug` name.
+    pub const ALL: [TokenType; 58] = [
+        TokenType::LeftParen,
+        TokenType::RightParen,
+        TokenType::LeftBrace,
+        TokenType::RightBrace,
+        TokenType::Comma,
+        TokenType::Dot,
+        TokenType::Minus,
+        TokenType::Plus,
+        TokenType::Semicolon,
+        TokenType::Question,
+        TokenType::Colon,
+        TokenType::Slash,
+        TokenType::Star,
+        TokenType::Percent,
+        TokenType::Ampersand,
+        TokenType::Pipe,
+        TokenType::Caret,
+        TokenType::Tilde,
+        TokenType::PlusPlus,
+        TokenType::MinusMinus,
+        TokenType::PlusEqual,
+        TokenType::MinusEqual,
+        TokenType::StarEqual,
+        TokenType::SlashEqual,
+        TokenType::PercentEqual,
+        TokenType::Bang,
+        TokenType::BangEqual,
+        TokenType::Equal,
+        TokenType::EqualEqual,
+        TokenType::Greater,
+        TokenType::GreaterEqual,
+        TokenType::Less,
+        TokenType::LessEqual,
+        TokenType::LessLess,
+        TokenType::GreaterGreater,
+        TokenType::StarStar,
+        TokenType::Identifier,
+        TokenType::String,
+        TokenType::Number,
+        TokenType::And,
+        TokenType::Assert,
+        TokenType::Class,
+        TokenType::Else,
+        TokenType::False,
+        TokenType::Func,
+        TokenType::For,
+        TokenType::If,
+        TokenType::Nil,
+        TokenType::Or,
+        TokenType::Print,
+        TokenType::Return,
+        TokenType::Super,
+        TokenType::Test,
+        TokenType::This,
+        TokenType::True,
+        TokenType::Var,
+        TokenType::While,
+        TokenType::Eof,
+    ];
+}
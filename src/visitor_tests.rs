@@ -0,0 +1,96 @@
+//! Tests for the visitor, mutable visitor and walker traits generated
+//! into `expr` and `stmt`, which cannot hold handwritten code themselves.
+
+use std::rc::Rc;
+
+use crate::expr::*;
+use crate::parser::*;
+use crate::scanner::*;
+use crate::stmt::*;
+use crate::token::*;
+use crate::token_type::*;
+
+/// Counts variable reads, overriding only the node it cares about.
+struct VariableCounter {
+    reads: Vec<String>,
+}
+
+impl ExprWalker<String> for VariableCounter {
+    fn walk_variable_expr(&mut self, expr: &VariableExpr) -> Result<(), String> {
+        if expr.name.lexeme() == "forbidden" {
+            return Err(format!("line {}: forbidden", expr.name.line()));
+        }
+        self.reads.push(expr.name.lexeme());
+        Ok(())
+    }
+}
+
+impl StmtWalker<String> for VariableCounter {}
+
+fn walk(source: &str) -> (Vec<String>, Result<(), String>) {
+    let mut scanner = Scanner::new(source.to_string());
+    let tokens = scanner.scan_tokens().unwrap();
+    let statements = Parser::new(tokens).parse().unwrap();
+    let mut counter = VariableCounter { reads: Vec::new() };
+    let result = statements.iter().try_for_each(|s| counter.walk_stmt(s));
+    (counter.reads, result)
+}
+
+#[test]
+fn test_walker_visits_nested_nodes() {
+    let (reads, result) = walk("var a = b + (c ? -d : e++); print a, f = g;");
+    assert!(result.is_ok());
+    assert_eq!(reads, ["b", "c", "d", "a", "g"]);
+}
+
+#[test]
+fn test_walker_stops_at_first_error() {
+    let (reads, result) = walk("print a;\nprint forbidden + b;");
+    assert_eq!(result, Err("line 2: forbidden".to_string()));
+    assert_eq!(reads, ["a"]);
+}
+
+/// A mutable visitor with its own error type.
+struct StatementKinds {
+    kinds: Vec<&'static str>,
+}
+
+impl StmtVisitorMut<usize, &'static str> for StatementKinds {
+    fn visit_assert_stmt(&mut self, _: &AssertStmt) -> Result<usize, &'static str> {
+        self.kinds.push("assert");
+        Ok(self.kinds.len())
+    }
+
+    fn visit_expression_stmt(&mut self, _: &ExpressionStmt) -> Result<usize, &'static str> {
+        self.kinds.push("expression");
+        Ok(self.kinds.len())
+    }
+
+    fn visit_print_stmt(&mut self, _: &PrintStmt) -> Result<usize, &'static str> {
+        self.kinds.push("print");
+        Ok(self.kinds.len())
+    }
+
+    fn visit_test_stmt(&mut self, _: &TestStmt) -> Result<usize, &'static str> {
+        Err("no declarations allowed")
+    }
+
+    fn visit_var_stmt(&mut self, _: &VarStmt) -> Result<usize, &'static str> {
+        Err("no declarations allowed")
+    }
+}
+
+#[test]
+fn test_mutable_visitor_with_custom_error() {
+    let nil = Token::new(TokenType::Nil, "nil".to_string(), None, 1);
+    let print = Stmt::print(Rc::new(Expr::literal(nil.clone(), None)));
+    let expression = Stmt::expression(Rc::new(Expr::literal(nil, None)));
+    let mut kinds = StatementKinds { kinds: Vec::new() };
+    assert_eq!(print.accept_mut(&mut kinds), Ok(1));
+    assert_eq!(expression.accept_mut(&mut kinds), Ok(2));
+    assert_eq!(kinds.kinds, ["print", "expression"]);
+
+    let name = Token::eof(1);
+    let var = Stmt::var(name, None, None);
+    assert_eq!(var.accept_mut(&mut kinds), Err("no declarations allowed"));
+}